@@ -1,8 +1,54 @@
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+
+const TEMPLATES_DIR: &str = "src/templates";
+
+fn collect_templates(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            collect_templates(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
+
 fn main() {
-    // we only need to bundle the templates with the
-    // feature is enabled.
-    #[cfg(feature = "bundled")]
-    {
-        minijinja_embed::embed_templates!("src/templates");
+    let mut files = Vec::new();
+    collect_templates(Path::new(TEMPLATES_DIR), &mut files);
+    files.sort();
+
+    // Fingerprint of the built-in templates, used by the build cache to notice
+    // that a new overture binary renders pages differently.
+    let mut hasher = DefaultHasher::new();
+    for path in &files {
+        path.hash(&mut hasher);
+        fs::read(path).unwrap().hash(&mut hasher);
+    }
+    println!(
+        "cargo:rustc-env=OVERTURE_TEMPLATES_HASH={:016x}",
+        hasher.finish()
+    );
+
+    // (name, source) of every built-in template, included by the `bundled`
+    // feature. Unlike an Environment this can be listed, which
+    // `overture eject-templates` needs.
+    let mut table = String::from("&[\n");
+    for path in &files {
+        let name = path.strip_prefix(TEMPLATES_DIR).unwrap();
+        table.push_str(&format!(
+            "    ({:?}, include_str!({:?})),\n",
+            name.to_string_lossy().replace('\\', "/"),
+            fs::canonicalize(path).unwrap()
+        ));
     }
+    table.push(']');
+    let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
+    fs::write(out_dir.join("templates.rs"), table).unwrap();
+
+    println!("cargo:rerun-if-changed={}", TEMPLATES_DIR);
 }
@@ -1,4 +1,6 @@
-use std::error::Error;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs;
 
 use chrono::DateTime;
 use chrono_tz::{Asia::Tokyo, Tz};
@@ -7,7 +9,12 @@ use serde::Serialize;
 
 use crate::articles::external;
 use crate::articles::local;
+use crate::articles::tag;
+use crate::cache;
 use crate::config;
+use crate::error::{Error, Result};
+use crate::markdown;
+use crate::project;
 
 #[derive(Debug, Clone, Serialize)]
 pub enum Source {
@@ -18,14 +25,14 @@ pub enum Source {
     HatenaBlog, // はてなブログ
 }
 
-impl ToString for Source {
-    fn to_string(&self) -> String {
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Source::Unknown => "Unknown".to_string(),
-            Source::Local => "Local".to_string(),
-            Source::Zenn => "Zenn".to_string(),
-            Source::ZennScraps => "Zenn Scraps".to_string(),
-            Source::HatenaBlog => "HatenaBlog".to_string(),
+            Source::Unknown => write!(f, "Unknown"),
+            Source::Local => write!(f, "Local"),
+            Source::Zenn => write!(f, "Zenn"),
+            Source::ZennScraps => write!(f, "Zenn Scraps"),
+            Source::HatenaBlog => write!(f, "HatenaBlog"),
         }
     }
 }
@@ -36,26 +43,46 @@ pub struct Article {
     pub url: String,
     pub source: Source,
     pub pub_date: String,
+    pub tags: Vec<tag::Tag>,
+    pub meta: serde_json::Value, // ローカルの記事の front matter、外部の記事は空
 }
 
+// Rendered local articles are kept in <cache>/articles by the hash of their
+// page, so feeds get the HTML of articles whose page wasn't rendered again.
+pub const CACHE_DIR: &str = "articles";
+
 pub struct Articles {
     local_articles: local::LocalArticles,
+    documents: BTreeMap<String, String>, // 記事の出力先ごとの HTML
     external_articles: external::ExternalArticles,
     env: minijinja::Environment<'static>,
     default_ctx: minijinja::Value,
+    renderer: markdown::Renderer,
+    paths: project::Paths,
+    include_drafts: bool,
+    minify: bool, // 記事のページを minify する
 }
 
 impl Articles {
-    pub async fn new(
-        external_rss_links: Vec<String>,
+    pub fn new(
+        external_articles: external::ExternalArticles,
         env: minijinja::Environment<'static>,
         default_ctx: minijinja::Value,
-    ) -> Result<Self, Box<dyn Error>> {
+        renderer: markdown::Renderer,
+        paths: project::Paths,
+        include_drafts: bool,
+        minify: bool,
+    ) -> Result<Self> {
         Ok(Self {
-            local_articles: local::LocalArticles::new()?,
-            external_articles: external::ExternalArticles::from_rss(external_rss_links).await?,
+            local_articles: local::LocalArticles::new(&paths.content, include_drafts)?,
+            documents: BTreeMap::new(),
+            external_articles,
             env,
             default_ctx,
+            renderer,
+            paths,
+            include_drafts,
+            minify,
         })
     }
 
@@ -66,16 +93,98 @@ impl Articles {
             .to_string()
     }
 
-    pub fn build_articles(&self) -> Result<(), ()> {
-        self.local_articles
-            .build_articles(&self.env, &self.default_ctx)
+    pub fn local_articles(&self) -> &[local::LocalArticle] {
+        &self.local_articles.articles
+    }
+
+    /// The articles of the external feeds, as fetched when the builder was made.
+    pub fn external_articles(&self) -> &external::ExternalArticles {
+        &self.external_articles
+    }
+
+    pub fn reload_local_articles(&mut self) -> Result<()> {
+        self.local_articles = local::LocalArticles::new(&self.paths.content, self.include_drafts)?;
+        Ok(())
+    }
+
+    pub fn build_articles(&mut self, cache: &mut cache::BuildCache, deps_hash: &str) -> Result<()> {
+        let output_dir = &self.paths.output;
+        let cache_dir = self.paths.cache.join(CACHE_DIR);
+        let mut documents = BTreeMap::new();
+        let mut hashes = HashSet::new();
+        let mut diagram_keys = HashSet::new();
+        for article in &self.local_articles.articles {
+            article.save_assets(cache, output_dir)?;
+            // diagrams stay cached while an article uses them, rendered this time or not
+            diagram_keys.extend(
+                self.renderer
+                    .diagram_keys(&article.raw_body, &article.settings()),
+            );
+
+            let output = article.output();
+            // images are part of the page, as the names of their <picture> variants
+            let mut inputs = vec![
+                deps_hash.as_bytes().to_vec(),
+                article.raw_body.clone().into_bytes(),
+            ];
+            let files = self
+                .renderer
+                .image_files(&article.raw_body, &article.settings());
+            for file in &files {
+                inputs.push(fs::read(file).map_err(|e| Error::io(file, e))?);
+                // variants are written before the page that names them, and
+                // stay while a page uses them, rendered this time or not
+                if let Some(pictures) = self.renderer.pictures() {
+                    let variants = pictures.save(file).map_err(|message| Error::Markdown {
+                        path: article.path.clone(),
+                        location: None,
+                        message: format!("image {}: {}", file.display(), message),
+                    })?;
+                    for variant in variants {
+                        cache.keep(&variant);
+                    }
+                }
+            }
+            let inputs: Vec<&[u8]> = inputs.iter().map(|input| input.as_slice()).collect();
+            let hash = cache::hash(&inputs);
+            let cached = cache_dir.join(format!("{}.html", hash));
+            hashes.insert(hash.clone());
+            if cache.is_fresh(&output, &hash) {
+                if let Ok(html) = fs::read_to_string(&cached) {
+                    documents.insert(output, html);
+                    continue;
+                }
+            }
+
+            let document = article.build(&self.renderer)?;
+            cache::write(&cached, &document.html);
+            documents.insert(output.clone(), document.html.clone());
+
+            let path = output_dir.join(&output);
+            article.save(&self.env, &self.default_ctx, document, &path, self.minify)?;
+        }
+        self.documents = documents;
+
+        for stale in cache.stale("articles/") {
+            let _ = fs::remove_file(output_dir.join(stale));
+        }
+        for stale in cache.stale(&format!("{}/", markdown::picture::OUTPUT_DIR)) {
+            let _ = fs::remove_file(output_dir.join(stale));
+        }
+        // rendered articles of old versions of pages
+        cache::prune(&cache_dir, &hashes);
+        if let Some(diagrams) = self.renderer.diagrams() {
+            diagrams.prune(&diagram_keys);
+        }
+        Ok(())
     }
 
-    pub fn generate_rss(&self, cfg: &config::Rss) -> Result<(), Box<dyn Error>> {
-        self.local_articles.generate_rss(cfg)
+    pub fn generate_feeds(&self, cfg: &config::Config) -> Result<()> {
+        self.local_articles
+            .generate_feeds(cfg, &self.documents, &self.renderer, &self.paths.output)
     }
 
-    pub fn aggregate_articles(&self) -> Result<Vec<Article>, Box<dyn Error>> {
+    pub fn aggregate_articles(&self) -> Result<Vec<Article>> {
         let mut articles = Vec::new();
 
         for article in &self.local_articles.articles {
@@ -84,6 +193,8 @@ impl Articles {
                 url: article.url_path(),
                 source: Source::Local,
                 pub_date: self.format_jst_pub_date(article.pub_date),
+                tags: tag::Tag::from_names(&article.options.tags),
+                meta: article.meta(),
             });
         }
 
@@ -93,6 +204,8 @@ impl Articles {
                 url: article.url.clone(),
                 source: article.source(),
                 pub_date: self.format_jst_pub_date(article.pub_date),
+                tags: Vec::new(),
+                meta: serde_json::Value::Object(Default::default()),
             });
         }
 
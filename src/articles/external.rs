@@ -1,12 +1,12 @@
-use std::error::Error;
-
 use chrono::DateTime;
 use chrono_tz::{Asia::Tokyo, Tz};
 use reqwest;
 use rss::Channel;
 
 use crate::articles::article::Source;
+use crate::error::{Error, Result};
 
+#[derive(Clone)]
 pub struct ExternalArticle {
     pub title: String,
     pub url: String,
@@ -36,17 +36,28 @@ impl ExternalArticle {
     }
 }
 
+#[derive(Clone)]
 pub struct ExternalArticles {
     pub articles: Vec<ExternalArticle>,
 }
 
 impl ExternalArticles {
-    pub async fn from_rss(urls: Vec<String>) -> Result<Self, Box<dyn Error>> {
+    pub async fn from_rss(urls: Vec<String>) -> Result<Self> {
         let mut articles = Vec::new();
 
         for url in urls {
-            let body = reqwest::get(url).await?.bytes().await?;
-            let channel = Channel::read_from(&body[..])?;
+            let rss_error = |message: String| Error::Rss {
+                url: url.clone(),
+                message,
+            };
+            let body = reqwest::get(&url)
+                .await
+                .and_then(|res| res.error_for_status())
+                .map_err(|e| rss_error(e.to_string()))?
+                .bytes()
+                .await
+                .map_err(|e| rss_error(e.to_string()))?;
+            let channel = Channel::read_from(&body[..]).map_err(|e| rss_error(e.to_string()))?;
 
             for item in channel.items() {
                 let title = match item.title() {
@@ -58,9 +69,9 @@ impl ExternalArticles {
                     None => continue,
                 };
                 let pub_date = match item.pub_date() {
-                    Some(pub_date) => {
-                        DateTime::parse_from_rfc2822(&pub_date)?.with_timezone(&Tokyo)
-                    }
+                    Some(pub_date) => DateTime::parse_from_rfc2822(pub_date)
+                        .map_err(|e| rss_error(format!("invalid pubDate {:?}: {}", pub_date, e)))?
+                        .with_timezone(&Tokyo),
                     None => continue,
                 };
 
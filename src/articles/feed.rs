@@ -0,0 +1,334 @@
+use atom_syndication as atom;
+use chrono::{DateTime, Utc};
+use chrono_tz::{Asia::Tokyo, Tz};
+use serde::Serialize;
+use serde_json::json;
+
+use crate::config::Feeds;
+
+// Feeds
+//
+// Every feed format is generated from the same list of entries, so the
+// formats only differ in how they are serialised.
+
+pub const RSS_FILE: &str = "rss.xml";
+pub const ATOM_FILE: &str = "atom.xml";
+pub const JSON_FILE: &str = "feed.json";
+
+pub struct Entry {
+    pub title: String,
+    pub link: String,
+    pub pub_date: DateTime<Tz>,
+    pub updated: Option<DateTime<Tz>>, // front matter の updated
+    pub summary: String,
+    pub content: Option<String>, // FeedContent::Full のときだけ記事の HTML が入る
+    pub tags: Vec<String>,
+}
+
+pub struct Channel<'a> {
+    pub title: &'a str,
+    pub description: &'a str,
+    pub link: &'a str,
+    pub author: &'a str, // プロフィールの名前
+    pub language: Option<&'a str>,
+    pub image: Option<&'a str>,
+    pub site_url: String,
+    pub entries: Vec<Entry>, // 新しい順
+}
+
+/// A `<link rel="alternate">` for `layout.html`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Link {
+    pub mime_type: &'static str,
+    pub title: String,
+    pub href: String,
+}
+
+pub fn links(feeds: &Feeds, title: &str) -> Vec<Link> {
+    let mut links = Vec::new();
+    if feeds.rss {
+        links.push(Link {
+            mime_type: "application/rss+xml",
+            title: format!("{} (RSS)", title),
+            href: format!("/{}", RSS_FILE),
+        });
+    }
+    if feeds.atom {
+        links.push(Link {
+            mime_type: "application/atom+xml",
+            title: format!("{} (Atom)", title),
+            href: format!("/{}", ATOM_FILE),
+        });
+    }
+    if feeds.json {
+        links.push(Link {
+            mime_type: "application/feed+json",
+            title: format!("{} (JSON Feed)", title),
+            href: format!("/{}", JSON_FILE),
+        });
+    }
+    links
+}
+
+impl Entry {
+    fn last_modified(&self) -> DateTime<Tz> {
+        self.updated.unwrap_or(self.pub_date)
+    }
+}
+
+impl Channel<'_> {
+    fn feed_url(&self, file: &str) -> String {
+        format!("{}/{}", self.site_url.trim_end_matches('/'), file)
+    }
+
+    fn updated(&self) -> DateTime<Tz> {
+        self.entries
+            .iter()
+            .map(Entry::last_modified)
+            .max()
+            .unwrap_or_else(|| Utc::now().with_timezone(&Tokyo))
+    }
+
+    pub fn rss(&self) -> String {
+        let items: Vec<rss::Item> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                rss::ItemBuilder::default()
+                    .title(entry.title.clone())
+                    .link(entry.link.clone())
+                    // the permalink never changes when a title is edited,
+                    // so readers don't show the article twice
+                    .guid(rss::Guid {
+                        value: entry.link.clone(),
+                        permalink: true,
+                    })
+                    .pub_date(entry.pub_date.to_rfc2822())
+                    .description(entry.summary.clone())
+                    .categories(
+                        entry
+                            .tags
+                            .iter()
+                            .map(|tag| rss::Category {
+                                name: tag.clone(),
+                                domain: None,
+                            })
+                            .collect::<Vec<_>>(),
+                    )
+                    // written as content:encoded
+                    .content(entry.content.clone())
+                    .build()
+            })
+            .collect();
+
+        rss::ChannelBuilder::default()
+            .title(self.title)
+            .description(self.description)
+            .link(self.link)
+            .language(self.language.map(str::to_string))
+            .last_build_date(self.updated().to_rfc2822())
+            .image(self.image.map(|url| rss::Image {
+                url: url.to_string(),
+                title: self.title.to_string(),
+                link: self.link.to_string(),
+                ..rss::Image::default()
+            }))
+            .items(items)
+            .build()
+            .to_string()
+    }
+
+    pub fn atom(&self) -> String {
+        let entries = self
+            .entries
+            .iter()
+            .map(|entry| atom::Entry {
+                title: atom::Text::plain(entry.title.clone()),
+                id: entry.link.clone(),
+                updated: entry.last_modified().fixed_offset(),
+                published: Some(entry.pub_date.fixed_offset()),
+                links: vec![atom::Link {
+                    href: entry.link.clone(),
+                    ..atom::Link::default()
+                }],
+                categories: entry
+                    .tags
+                    .iter()
+                    .map(|tag| atom::Category {
+                        term: tag.clone(),
+                        ..atom::Category::default()
+                    })
+                    .collect(),
+                summary: Some(atom::Text::plain(entry.summary.clone())),
+                content: entry.content.as_ref().map(|content| atom::Content {
+                    value: Some(content.clone()),
+                    content_type: Some("html".to_string()),
+                    ..atom::Content::default()
+                }),
+                ..atom::Entry::default()
+            })
+            .collect();
+
+        atom::Feed {
+            title: atom::Text::plain(self.title),
+            subtitle: Some(atom::Text::plain(self.description)),
+            id: self.site_url.clone(),
+            updated: self.updated().fixed_offset(),
+            // entries without an author of their own take the feed's
+            authors: vec![atom::Person {
+                name: self.author.to_string(),
+                ..atom::Person::default()
+            }],
+            links: vec![
+                atom::Link {
+                    href: self.site_url.clone(),
+                    rel: "alternate".to_string(),
+                    ..atom::Link::default()
+                },
+                atom::Link {
+                    href: self.feed_url(ATOM_FILE),
+                    rel: "self".to_string(),
+                    ..atom::Link::default()
+                },
+            ],
+            entries,
+            ..atom::Feed::default()
+        }
+        .to_string()
+    }
+
+    /// Serialises the channel as JSON Feed 1.1.
+    pub fn json(&self) -> String {
+        let items: Vec<serde_json::Value> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                let mut item = json!({
+                    "id": entry.link,
+                    "url": entry.link,
+                    "title": entry.title,
+                    "summary": entry.summary,
+                    "date_published": entry.pub_date.to_rfc3339(),
+                    "tags": entry.tags,
+                });
+                if let Some(updated) = entry.updated {
+                    item["date_modified"] = json!(updated.to_rfc3339());
+                }
+                // an item needs either content_html or content_text
+                match &entry.content {
+                    Some(content) => item["content_html"] = json!(content),
+                    None => item["content_text"] = json!(entry.summary),
+                }
+                item
+            })
+            .collect();
+
+        json!({
+            "version": "https://jsonfeed.org/version/1.1",
+            "title": self.title,
+            "description": self.description,
+            "home_page_url": self.site_url,
+            "feed_url": self.feed_url(JSON_FILE),
+            "authors": [{ "name": self.author }],
+            "items": items,
+        })
+        .to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(content: Option<&str>) -> Channel<'static> {
+        Channel {
+            title: "ucpr.dev",
+            description: "ucpr's articles",
+            link: "https://www.ucpr.dev",
+            author: "ucpr",
+            language: Some("ja"),
+            image: Some("https://www.ucpr.dev/statics/icon.png"),
+            site_url: "https://www.ucpr.dev/".to_string(),
+            entries: vec![Entry {
+                title: "テスト記事".to_string(),
+                link: "https://www.ucpr.dev/articles/test".to_string(),
+                pub_date: DateTime::parse_from_rfc3339("2024-04-25T00:00:00+09:00")
+                    .unwrap()
+                    .with_timezone(&Tokyo),
+                updated: Some(
+                    DateTime::parse_from_rfc3339("2024-05-01T00:00:00+09:00")
+                        .unwrap()
+                        .with_timezone(&Tokyo),
+                ),
+                summary: "summary".to_string(),
+                content: content.map(|content| content.to_string()),
+                tags: vec!["Rust".to_string()],
+            }],
+        }
+    }
+
+    #[test]
+    fn test_rss() {
+        let channel: rss::Channel = channel(Some("<p>body</p>")).rss().parse().unwrap();
+        assert_eq!(channel.language(), Some("ja"));
+        assert_eq!(
+            channel.last_build_date(),
+            Some("Wed, 1 May 2024 00:00:00 +0900")
+        );
+        assert_eq!(
+            channel.image().map(|image| image.url()),
+            Some("https://www.ucpr.dev/statics/icon.png")
+        );
+
+        let item = &channel.items()[0];
+        assert_eq!(
+            item.guid().map(|guid| (guid.value(), guid.is_permalink())),
+            Some(("https://www.ucpr.dev/articles/test", true))
+        );
+        assert_eq!(item.categories()[0].name(), "Rust");
+        assert_eq!(item.content(), Some("<p>body</p>"));
+    }
+
+    #[test]
+    fn test_atom() {
+        let feed: atom::Feed = channel(Some("<p>body</p>")).atom().parse().unwrap();
+        assert_eq!(feed.id, "https://www.ucpr.dev/");
+        assert_eq!(feed.links[1].href, "https://www.ucpr.dev/atom.xml");
+        assert_eq!(feed.authors[0].name, "ucpr");
+        assert_eq!(feed.entries.len(), 1);
+        assert_eq!(
+            feed.entries[0].updated.to_rfc3339(),
+            "2024-05-01T00:00:00+09:00"
+        );
+        assert_eq!(
+            feed.entries[0].published.map(|date| date.to_rfc3339()),
+            Some("2024-04-25T00:00:00+09:00".to_string())
+        );
+        assert_eq!(feed.entries[0].categories[0].term, "Rust");
+        assert_eq!(
+            feed.entries[0].content.as_ref().unwrap().value.as_deref(),
+            Some("<p>body</p>")
+        );
+    }
+
+    #[test]
+    fn test_json() {
+        let feed: serde_json::Value = serde_json::from_str(&channel(None).json()).unwrap();
+        assert_eq!(feed["version"], "https://jsonfeed.org/version/1.1");
+        assert_eq!(feed["feed_url"], "https://www.ucpr.dev/feed.json");
+        assert_eq!(feed["items"][0]["content_text"], "summary");
+        assert_eq!(
+            feed["items"][0]["date_published"],
+            "2024-04-25T00:00:00+09:00"
+        );
+        assert_eq!(
+            feed["items"][0]["date_modified"],
+            "2024-05-01T00:00:00+09:00"
+        );
+        assert_eq!(feed["authors"][0]["name"], "ucpr");
+
+        let feed: serde_json::Value =
+            serde_json::from_str(&channel(Some("<p>body</p>")).json()).unwrap();
+        assert_eq!(feed["items"][0]["content_html"], "<p>body</p>");
+    }
+}
@@ -1,33 +1,113 @@
-use std::error::Error;
+use std::collections::BTreeMap;
 use std::fs;
-use std::io::prelude::*;
+use std::path::{Path, PathBuf};
 
-use chrono::DateTime;
+use chrono::{DateTime, Utc};
 use chrono_tz::{Asia::Tokyo, Tz};
 use markdown;
 use minijinja::context;
-use rss::ChannelBuilder;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-use crate::config::Rss;
+use crate::articles::feed;
+use crate::articles::tag::Tag;
+use crate::assets;
+use crate::cache;
+use crate::config::{Config, FeedContent};
+use crate::error::{Error, Location, Result};
+use crate::markdown as render;
 
-#[derive(Debug, Deserialize)]
+/// The front matter of an article. Templates get all of it as `page.meta`.
+#[derive(Debug, Deserialize, Serialize)]
 pub struct Options {
     pub title: String,
     pub description: String,
     pub date: String,
+    pub updated: Option<String>,
     pub tags: Vec<String>,
+    #[serde(default)]
+    pub draft: bool,
+    #[serde(default = "default_toc")]
+    pub toc: bool,
+    pub math: Option<bool>, // None のときは config の [markdown] math
+    /// Keys not listed above, also passed to the template as `page.extra`.
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
 }
 
+fn default_toc() -> bool {
+    true
+}
+
+/// The markdown file of a page bundle, the directory it is in.
+pub const BUNDLE_INDEX: &str = "index.md";
+
 pub struct LocalArticle {
+    pub path: PathBuf,
+    pub bundle: Option<PathBuf>, // ページバンドルのときはそのディレクトリ
     pub raw_body: String,
     pub raw_file_name: String,
     pub pub_date: DateTime<Tz>,
+    pub updated: Option<DateTime<Tz>>,
     pub options: Options,
 }
 
+// TOML datetimes would reach `extra` as `{"$__toml_private_datetime": ...}`,
+// so they are written as strings, like YAML ones.
+fn stringify_datetimes(value: &mut toml::Value) {
+    match value {
+        toml::Value::Datetime(datetime) => *value = toml::Value::String(datetime.to_string()),
+        toml::Value::Array(values) => values.iter_mut().for_each(stringify_datetimes),
+        toml::Value::Table(table) => table
+            .iter_mut()
+            .for_each(|(_, value)| stringify_datetimes(value)),
+        _ => {}
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Format {
+    Toml, // +++ で囲む
+    Yaml, // --- で囲む
+}
+
+// Front matter of an article, along with the line of the article it starts on.
+struct FrontMatter {
+    format: Format,
+    value: String,
+    first_line: usize,
+}
+
+impl FrontMatter {
+    // Location of the first line that assigns `key`, e.g. `date = "..."` or
+    // `date: ...`.
+    fn key_location(&self, key: &str) -> Option<Location> {
+        let separator = match self.format {
+            Format::Toml => '=',
+            Format::Yaml => ':',
+        };
+        self.value
+            .lines()
+            .position(|line| {
+                line.trim_start()
+                    .strip_prefix(key)
+                    .is_some_and(|rest| rest.trim_start().starts_with(separator))
+            })
+            .map(|index| Location::line(self.first_line + index))
+    }
+
+    fn date(&self, path: &Path, key: &str, value: &str) -> Result<DateTime<Tz>> {
+        DateTime::parse_from_rfc3339(value)
+            .map(|date| date.with_timezone(&Tokyo))
+            .map_err(|e| Error::FrontMatter {
+                path: path.to_path_buf(),
+                location: self.key_location(key),
+                message: format!("invalid {} {:?}: {}", key, value, e),
+            })
+    }
+}
+
 impl LocalArticle {
-    fn options(raw_body: &str) -> Result<Options, toml::de::Error> {
+    fn front_matter(path: &Path, raw_body: &str) -> Result<FrontMatter> {
         let config = &markdown::ParseOptions {
             constructs: markdown::Constructs {
                 frontmatter: true,
@@ -35,87 +115,238 @@ impl LocalArticle {
             },
             ..markdown::ParseOptions::default()
         };
-        let tree = markdown::to_mdast(raw_body, config).ok().unwrap();
+        let tree = markdown::to_mdast(raw_body, config).map_err(|e| render::error(path, e))?;
 
-        let mut front_matter: &str = "";
+        let mut front_matter = None;
         tree.children().into_iter().for_each(|node| {
             for child in node.iter() {
-                match child {
+                let (format, value, position) = match child {
                     markdown::mdast::Node::Toml(toml) => {
-                        front_matter = &toml.value;
-                        break;
+                        (Format::Toml, &toml.value, &toml.position)
                     }
-                    _ => {}
-                }
+                    markdown::mdast::Node::Yaml(yaml) => {
+                        (Format::Yaml, &yaml.value, &yaml.position)
+                    }
+                    _ => continue,
+                };
+                front_matter = Some(FrontMatter {
+                    format,
+                    value: value.clone(),
+                    // the value starts on the line after the opening `+++` or `---`
+                    first_line: position.as_ref().map_or(1, |p| p.start.line) + 1,
+                });
+                break;
             }
         });
 
-        toml::from_str(front_matter)
+        front_matter.ok_or_else(|| Error::FrontMatter {
+            path: path.to_path_buf(),
+            location: None,
+            message: "missing front matter, expected TOML between +++ or YAML between ---"
+                .to_string(),
+        })
+    }
+
+    fn options(path: &Path, front_matter: &FrontMatter) -> Result<Options> {
+        let error = |location, message| Error::FrontMatter {
+            path: path.to_path_buf(),
+            location,
+            message,
+        };
+        match front_matter.format {
+            Format::Toml => {
+                let toml_error = |e: toml::de::Error| {
+                    let location = e.span().map(|span| {
+                        Location::from_offset(
+                            &front_matter.value,
+                            span.start,
+                            front_matter.first_line,
+                        )
+                    });
+                    error(location, e.message().to_string())
+                };
+                let mut value: toml::Value =
+                    toml::from_str(&front_matter.value).map_err(toml_error)?;
+                stringify_datetimes(&mut value);
+                value.try_into().map_err(|e| {
+                    // only the text knows where the error is
+                    toml_error(
+                        toml::from_str::<Options>(&front_matter.value)
+                            .err()
+                            .unwrap_or(e),
+                    )
+                })
+            }
+            Format::Yaml => serde_yaml::from_str(&front_matter.value).map_err(|e| {
+                let location = e.location().map(|location| Location {
+                    line: front_matter.first_line + location.line() - 1,
+                    column: Some(location.column()),
+                });
+                // the location is reported separately
+                let message = e.to_string();
+                let message = match message.rsplit_once(" at line ") {
+                    Some((message, _)) if location.is_some() => message.to_string(),
+                    _ => message,
+                };
+                error(location, message)
+            }),
+        }
+    }
+
+    /// Every key of the front matter, typed ones and extra ones alike.
+    pub fn meta(&self) -> serde_json::Value {
+        // the keys are strings, so this can't fail
+        serde_json::to_value(&self.options).unwrap_or_default()
+    }
+
+    /// When the article was last modified, for sitemaps.
+    pub fn last_modified(&self) -> DateTime<Tz> {
+        self.updated.unwrap_or(self.pub_date)
+    }
+
+    /// Drafts and articles dated after `now` are not published.
+    pub fn is_published(&self, now: DateTime<Tz>) -> bool {
+        !self.options.draft && self.pub_date <= now
+    }
+
+    /// The name of the article in its URL: the file name without extension,
+    /// or the directory name of a bundle.
+    pub fn slug(&self) -> String {
+        match self.bundle.as_ref().and_then(|dir| dir.file_name()) {
+            Some(name) => name.to_string_lossy().to_string(),
+            None => self.raw_file_name.split('.').next().unwrap().to_string(),
+        }
     }
 
     pub fn url_path(&self) -> String {
-        format!(
-            "/articles/{}",
-            self.raw_file_name.split('.').next().unwrap()
-        )
+        format!("/articles/{}", self.slug())
     }
 
-    pub fn from_file(path: &str) -> Result<LocalArticle, std::io::Error> {
-        let raw_file_name = path.split('/').last().unwrap().to_string();
-        let raw_body = std::fs::read_to_string(path)?;
-        let options = LocalArticle::options(&raw_body).unwrap();
-        let pub_date = DateTime::parse_from_rfc3339(&options.date)
-            .unwrap()
-            .with_timezone(&Tokyo);
+    /// The page of the article, relative to the output directory. A bundle's
+    /// page goes in a directory of its own, next to its assets.
+    pub fn output(&self) -> String {
+        match self.bundle {
+            Some(_) => format!("articles/{}/index.html", self.slug()),
+            None => format!("articles/{}.html", self.slug()),
+        }
+    }
+
+    /// Reads the page bundle `dir`, whose article is its `index.md`.
+    pub fn from_dir(dir: &Path) -> Result<LocalArticle> {
+        let mut article = LocalArticle::from_file(&dir.join(BUNDLE_INDEX))?;
+        article.bundle = Some(dir.to_path_buf());
+        Ok(article)
+    }
+
+    pub fn from_file(path: &Path) -> Result<LocalArticle> {
+        let raw_file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let raw_body = fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+        let front_matter = LocalArticle::front_matter(path, &raw_body)?;
+        let options = LocalArticle::options(path, &front_matter)?;
+        let pub_date = front_matter.date(path, "date", &options.date)?;
+        let updated = match &options.updated {
+            Some(updated) => Some(front_matter.date(path, "updated", updated)?),
+            None => None,
+        };
 
         Ok(LocalArticle {
+            path: path.to_path_buf(),
+            bundle: None,
             raw_file_name,
             raw_body,
             options,
             pub_date,
+            updated,
         })
     }
 
-    fn build(&self) -> String {
-        let opts = markdown::Options {
-            parse: markdown::ParseOptions {
-                constructs: markdown::Constructs {
-                    frontmatter: true,
-                    ..markdown::Constructs::gfm()
-                },
-                ..markdown::ParseOptions::gfm()
-            },
-            ..markdown::Options::gfm()
-        };
+    /// How the article is rendered.
+    pub fn settings(&self) -> render::Settings {
+        render::Settings {
+            math: self.options.math,
+            // the assets of a bundle are next to its page
+            base_url: self.bundle.as_ref().map(|_| self.url_path()),
+        }
+    }
 
-        let mut body = self.raw_body.clone();
-        body = markdown::to_html_with_options(&body, &opts).unwrap();
+    pub fn build(&self, renderer: &render::Renderer) -> Result<render::Document> {
+        let body = renderer.render(&self.path, &self.raw_body, &self.settings())?;
 
-        format!("<h1>{}</h1><div>{}</div>", self.options.title, body)
+        Ok(render::Document {
+            html: format!(
+                "<h1{}>{}</h1><div>{}</div>",
+                renderer
+                    .classes()
+                    .get("h1")
+                    .map(|class| format!(" class=\"{}\"", render::html::escape(class)))
+                    .unwrap_or_default(),
+                self.options.title,
+                body.html
+            ),
+            toc: body.toc,
+        })
+    }
+
+    /// Copies the files of a bundle other than its article next to its page.
+    pub fn save_assets(&self, cache: &mut cache::BuildCache, output_dir: &Path) -> Result<()> {
+        let Some(dir) = &self.bundle else {
+            return Ok(());
+        };
+        let mut files = Vec::new();
+        cache::collect_files(dir, dir, &mut files)?;
+        for file in files {
+            if file == Path::new(BUNDLE_INDEX) {
+                continue;
+            }
+            let src = dir.join(&file);
+            let contents = fs::read(&src).map_err(|e| Error::io(&src, e))?;
+            let output = Path::new("articles").join(self.slug()).join(&file);
+            if cache.is_fresh(&output.to_string_lossy(), &cache::hash(&[&contents])) {
+                continue;
+            }
+
+            let dest = output_dir.join(output);
+            if let Some(parent) = dest.parent() {
+                fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
+            }
+            fs::write(&dest, contents).map_err(|e| Error::io(&dest, e))?;
+        }
+        Ok(())
     }
 
     pub fn save(
         &self,
         env: &minijinja::Environment<'static>,
         default_ctx: &minijinja::Value,
-        path: &str,
-    ) -> Result<(), std::io::Error> {
-        let html = self.build();
+        document: render::Document,
+        path: &Path,
+        minify: bool,
+    ) -> Result<()> {
+        let toc = if self.options.toc {
+            document.toc
+        } else {
+            Vec::new()
+        };
 
-        let template = env.get_template("article.html").unwrap();
-        let base = self.raw_file_name.split('.').next().unwrap();
+        let template = env.get_template("article.html")?;
         let page = context! {
             ..context!{
-                content => html,
-                url_path => format!("/articles/{}", base),
+                content => document.html,
+                toc => toc,
+                meta => self.meta(),
+                extra => self.options.extra,
+                url_path => self.url_path(),
                 title => self.options.title,
                 description => self.options.description,
+                tags => Tag::from_names(&self.options.tags),
             },
             ..default_ctx.clone(),
         };
-        let content = template.render(context!(page)).unwrap();
-
-        std::fs::write(path, content)
+        let content = template.render(context!(page))?;
+        assets::write_page(path, &content, minify)
     }
 }
 
@@ -124,63 +355,307 @@ pub struct LocalArticles {
 }
 
 impl LocalArticles {
-    pub fn new() -> Result<Self, Box<dyn Error>> {
+    /// Reads every article in `dir`. Unless `include_drafts` is set,
+    /// drafts and future-dated articles are skipped. Directories without an
+    /// `index.md` aren't page bundles, e.g. images shared by articles, and
+    /// files other than `.md` ones, e.g. editor swap files, aren't articles.
+    pub fn new(dir: &Path, include_drafts: bool) -> Result<Self> {
         let mut articles = Vec::new();
+        let now = Utc::now().with_timezone(&Tokyo);
 
-        let paths = std::fs::read_dir("articles")?;
+        let paths = fs::read_dir(dir).map_err(|e| Error::io(dir, e))?;
         for path in paths {
-            let path = path?.path();
-            let path = match path.to_str() {
-                Some(path) => path,
-                None => continue,
+            let path = path.map_err(|e| Error::io(dir, e))?.path();
+            let hidden = path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+            if hidden {
+                continue;
+            }
+            let article = if path.is_dir() {
+                if !path.join(BUNDLE_INDEX).is_file() {
+                    continue;
+                }
+                LocalArticle::from_dir(&path)?
+            } else if path.extension().is_some_and(|ext| ext == "md") {
+                LocalArticle::from_file(&path)?
+            } else {
+                continue;
             };
-            let article = LocalArticle::from_file(path)?;
+            if !include_drafts && !article.is_published(now) {
+                continue;
+            }
 
             articles.push(article);
         }
         Ok(Self { articles })
     }
 
-    pub fn build_articles(
+    fn feed_entries(
         &self,
-        env: &minijinja::Environment<'static>,
-        default_ctx: &minijinja::Value,
-    ) -> Result<(), ()> {
+        site_url: &url::Url,
+        content: FeedContent,
+        documents: &BTreeMap<String, String>,
+        renderer: &render::Renderer,
+    ) -> Result<Vec<feed::Entry>> {
+        // feed readers don't know which site root-relative URLs are on, and
+        // links are on the same one
+        let origin = site_url.origin().ascii_serialization();
+        let mut entries = Vec::new();
+
         for article in &self.articles {
-            let path = format!(
-                "generates/articles/{}.html",
-                article.raw_file_name.split('.').next().unwrap()
-            );
-            article.save(env, default_ctx, &path).unwrap();
+            let link = site_url
+                .join(&article.url_path())
+                .map_err(|source| Error::Url {
+                    url: site_url.to_string(),
+                    source,
+                })?;
+            let content = match content {
+                // only articles that weren't built are rendered here
+                FeedContent::Full => Some(render::link::absolutize_html(
+                    &match documents.get(&article.output()) {
+                        Some(html) => html.clone(),
+                        None => article.build(renderer)?.html,
+                    },
+                    &origin,
+                )),
+                FeedContent::Summary => None,
+            };
+            entries.push(feed::Entry {
+                title: article.options.title.clone(),
+                link: link.to_string(),
+                pub_date: article.pub_date,
+                updated: article.updated,
+                summary: article.options.description.clone(),
+                content,
+                tags: article.options.tags.clone(),
+            });
+        }
+
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.pub_date));
+        Ok(entries)
+    }
+
+    pub fn generate_feeds(
+        &self,
+        cfg: &Config,
+        documents: &BTreeMap<String, String>,
+        renderer: &render::Renderer,
+        output_dir: &Path,
+    ) -> Result<()> {
+        let (rss, feeds) = (&cfg.rss, &cfg.feeds);
+        let site_url = url::Url::parse(&cfg.url).map_err(|source| Error::Url {
+            url: cfg.url.clone(),
+            source,
+        })?;
+        let channel = feed::Channel {
+            title: &rss.title,
+            description: &rss.description,
+            link: &rss.url,
+            author: &cfg.profile.name,
+            language: rss.language.as_deref(),
+            image: rss.image.as_deref(),
+            site_url: site_url
+                .join("/")
+                .map_err(|source| Error::Url {
+                    url: cfg.url.clone(),
+                    source,
+                })?
+                .to_string(),
+            entries: self.feed_entries(&site_url, feeds.content, documents, renderer)?,
+        };
+
+        let outputs = [
+            (feeds.rss, feed::RSS_FILE, channel.rss()),
+            (feeds.atom, feed::ATOM_FILE, channel.atom()),
+            (feeds.json, feed::JSON_FILE, channel.json()),
+        ];
+        for (enabled, file, content) in outputs {
+            let path = output_dir.join(file);
+            if !enabled {
+                // a feed that was turned off should not keep being served
+                let _ = fs::remove_file(&path);
+                continue;
+            }
+            fs::write(&path, content).map_err(|e| Error::io(&path, e))?;
         }
         Ok(())
     }
+}
 
-    pub fn generate_rss(&self, config: &Rss) -> Result<(), Box<dyn Error>> {
-        let mut items = Vec::new();
-        let base_url = url::Url::parse(&config.url)?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        for article in &self.articles {
-            let path = article.raw_file_name.split('.').next().unwrap();
-            let link = base_url.join(&format!("/articles/{}", path))?;
-            items.push(
-                rss::ItemBuilder::default()
-                    .title(article.options.title.clone())
-                    .link(link.to_string())
-                    .pub_date(article.pub_date.to_rfc2822())
-                    .description(article.options.description.clone())
-                    .build(),
-            );
+    fn article(date: &str, draft: bool) -> LocalArticle {
+        LocalArticle {
+            path: PathBuf::from("articles/test.md"),
+            bundle: None,
+            raw_body: String::new(),
+            raw_file_name: "test.md".to_string(),
+            pub_date: DateTime::parse_from_rfc3339(date)
+                .unwrap()
+                .with_timezone(&Tokyo),
+            updated: None,
+            options: Options {
+                title: "test".to_string(),
+                description: "test".to_string(),
+                date: date.to_string(),
+                updated: None,
+                tags: Vec::new(),
+                draft,
+                toc: true,
+                math: None,
+                extra: BTreeMap::new(),
+            },
         }
+    }
 
-        let channel = ChannelBuilder::default()
-            .title(&config.title)
-            .description(&config.description)
-            .link(&config.url)
-            .items(items)
-            .build();
-        let mut file = fs::File::create("generates/rss.xml")?;
-        file.write_all(channel.to_string().as_bytes())?;
-        Ok(())
+    #[test]
+    fn test_is_published() {
+        let now = DateTime::parse_from_rfc3339("2024-04-01T00:00:00+09:00")
+            .unwrap()
+            .with_timezone(&Tokyo);
+
+        assert!(article("2024-03-31T00:00:00+09:00", false).is_published(now));
+        assert!(article("2024-04-01T00:00:00+09:00", false).is_published(now));
+        assert!(!article("2024-04-02T00:00:00+09:00", false).is_published(now));
+        assert!(!article("2024-03-31T00:00:00+09:00", true).is_published(now));
+    }
+
+    #[test]
+    fn test_front_matter() {
+        let path = Path::new("articles/test.md");
+        let toml = "+++\ntitle = \"a\"\ndescription = \"b\"\ndate = \"2024-04-01T00:00:00+09:00\"\ntags = [\"c\"]\nseries = \"d\"\nreviewed = [2024-04-02]\n+++\n\nbody\n";
+        let yaml = "---\ntitle: a\ndescription: b\ndate: 2024-04-01T00:00:00+09:00\ntags:\n  - c\nseries: d\nreviewed:\n  - 2024-04-02\n---\n\nbody\n";
+
+        for source in [toml, yaml] {
+            let front_matter = LocalArticle::front_matter(path, source).unwrap();
+            let options = LocalArticle::options(path, &front_matter).unwrap();
+            assert_eq!(options.title, "a");
+            assert_eq!(options.date, "2024-04-01T00:00:00+09:00");
+            assert_eq!(options.tags, ["c"]);
+            assert_eq!(options.extra["series"], "d");
+
+            let article = LocalArticle {
+                options,
+                ..article("2024-04-01T00:00:00+09:00", false)
+            };
+            let meta = article.meta();
+            assert_eq!(meta["title"], "a");
+            assert_eq!(meta["tags"][0], "c");
+            assert_eq!(meta["series"], "d");
+            assert_eq!(meta["reviewed"][0], "2024-04-02");
+        }
+
+        let yaml = "---\ntitle: a\ndescription: [b\n---\n";
+        let front_matter = LocalArticle::front_matter(path, yaml).unwrap();
+        let Err(Error::FrontMatter { location, .. }) = LocalArticle::options(path, &front_matter)
+        else {
+            panic!("invalid YAML was accepted");
+        };
+        assert_eq!(location.map(|location| location.line), Some(3));
+
+        // a wrong type is only caught after the datetimes are converted
+        let toml = "+++\ntitle = \"a\"\ndescription = 1\n+++\n";
+        let front_matter = LocalArticle::front_matter(path, toml).unwrap();
+        let Err(Error::FrontMatter { location, .. }) = LocalArticle::options(path, &front_matter)
+        else {
+            panic!("invalid TOML was accepted");
+        };
+        assert_eq!(location.map(|location| location.line), Some(3));
+    }
+
+    #[test]
+    fn test_bundle() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let bundle = dir.join("my-post");
+        fs::create_dir_all(&bundle).unwrap();
+        let source = "+++\ntitle = \"a\"\ndescription = \"b\"\ndate = \"2024-04-01T00:00:00+09:00\"\ntags = []\n+++\n\n![](diagram.png)\n";
+        fs::write(bundle.join(BUNDLE_INDEX), source).unwrap();
+        fs::write(bundle.join("diagram.png"), "png").unwrap();
+        fs::write(dir.join("plain.md"), source).unwrap();
+        fs::create_dir(dir.join("shared")).unwrap();
+        fs::write(dir.join("shared/logo.png"), "png").unwrap();
+
+        let mut articles = LocalArticles::new(dir, false).unwrap().articles;
+        articles.sort_by_key(|article| article.slug());
+        assert_eq!(articles.len(), 2);
+        assert_eq!(articles[0].url_path(), "/articles/my-post");
+        assert_eq!(articles[0].output(), "articles/my-post/index.html");
+        assert_eq!(articles[1].url_path(), "/articles/plain");
+        assert_eq!(articles[1].output(), "articles/plain.html");
+
+        let output_dir = dir.join("generates");
+        let mut cache = cache::BuildCache::load(output_dir.join(cache::CACHE_FILE), false);
+        articles[0].save_assets(&mut cache, &output_dir).unwrap();
+        assert_eq!(
+            fs::read_to_string(output_dir.join("articles/my-post/diagram.png")).unwrap(),
+            "png"
+        );
+        assert!(!output_dir
+            .join("articles/my-post")
+            .join(BUNDLE_INDEX)
+            .exists());
+    }
+
+    #[test]
+    fn test_generate_feeds() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let mut config = Config {
+            url: "https://www.example.com".to_string(),
+            ..Config::default()
+        };
+        // the channel's link may be elsewhere, entries are on the site
+        config.rss.url = "https://feeds.example.net/rss".to_string();
+        config.feeds.content = FeedContent::Full;
+        let paths = crate::project::Paths::new(dir, dir.join("config.toml"), &config.paths, None);
+        let renderer =
+            render::Renderer::new(&config, minijinja::Environment::new(), &paths).unwrap();
+
+        let articles = LocalArticles {
+            articles: vec![article("2024-04-01T00:00:00+09:00", false)],
+        };
+        let documents = BTreeMap::from([(
+            articles.articles[0].output(),
+            "<a href=\"/articles/other\">other</a>".to_string(),
+        )]);
+        articles
+            .generate_feeds(&config, &documents, &renderer, dir)
+            .unwrap();
+
+        let channel: rss::Channel = fs::read_to_string(dir.join(feed::RSS_FILE))
+            .unwrap()
+            .parse()
+            .unwrap();
+        let item = &channel.items()[0];
+        assert_eq!(item.link(), Some("https://www.example.com/articles/test"));
+        assert_eq!(
+            item.content(),
+            Some("<a href=\"https://www.example.com/articles/other\">other</a>")
+        );
+    }
+
+    #[test]
+    fn test_skips_other_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let source = "+++\ntitle = \"a\"\ndescription = \"b\"\ndate = \"2024-04-01T00:00:00+09:00\"\ntags = []\n+++\n";
+        fs::write(dir.join("post.md"), source).unwrap();
+        for name in [
+            ".post.md.swp",
+            "#post.md#",
+            ".DS_Store",
+            "README",
+            ".draft.md",
+        ] {
+            fs::write(dir.join(name), "not an article").unwrap();
+        }
+
+        let articles = LocalArticles::new(dir, false).unwrap().articles;
+        assert_eq!(articles.len(), 1);
+        assert_eq!(articles[0].slug(), "post");
     }
 }
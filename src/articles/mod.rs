@@ -1,3 +1,5 @@
 pub mod article;
 pub mod external;
+pub mod feed;
 pub mod local;
+pub mod tag;
@@ -0,0 +1,136 @@
+use serde::Serialize;
+
+use crate::articles::article::Article;
+use crate::cache;
+use crate::slug;
+
+// tags/index.html is the list of tags
+const RESERVED_SLUGS: [&str; 1] = ["index"];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Tag {
+    pub name: String,
+    pub slug: String,
+    pub url: String,
+}
+
+// The slug of the tag `name`. When slugifying drops more than case and
+// spaces, e.g. `C++` and `C#` would both be `c`, or the slug is reserved,
+// a hash of the name is appended, so every article links the same page.
+fn tag_slug(name: &str) -> String {
+    let slug = slug::slugify(name);
+    let lowercase = name.to_lowercase();
+    let words: Vec<&str> = lowercase.split_whitespace().collect();
+    if slug.is_empty() || (words.join("-") == slug && !RESERVED_SLUGS.contains(&slug.as_str())) {
+        return slug;
+    }
+    let hash = cache::hash(&[lowercase.as_bytes()]);
+    format!("{}-{}", slug, &hash[..8])
+}
+
+impl Tag {
+    pub fn new(name: &str) -> Self {
+        let slug = tag_slug(name);
+        Tag {
+            name: name.to_string(),
+            url: format!("/tags/{}", slug),
+            slug,
+        }
+    }
+
+    pub fn from_names(names: &[String]) -> Vec<Tag> {
+        names
+            .iter()
+            .map(|name| Tag::new(name))
+            .filter(|tag| !tag.slug.is_empty())
+            .collect()
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TaggedArticles<'a> {
+    pub tag: Tag,
+    pub count: usize,
+    pub articles: Vec<&'a Article>,
+}
+
+/// Groups `articles` by tag, most used tags first.
+///
+/// Tags are matched by slug, so `Rust` and `rust` end up on the same page
+/// under the name that was seen first.
+pub fn group_by_tag(articles: &[Article]) -> Vec<TaggedArticles<'_>> {
+    let mut groups: Vec<TaggedArticles> = Vec::new();
+
+    for article in articles {
+        for tag in &article.tags {
+            match groups.iter_mut().find(|group| group.tag.slug == tag.slug) {
+                Some(group) => group.articles.push(article),
+                None => groups.push(TaggedArticles {
+                    tag: tag.clone(),
+                    count: 0,
+                    articles: vec![article],
+                }),
+            }
+        }
+    }
+
+    for group in &mut groups {
+        group.count = group.articles.len();
+    }
+    groups.sort_by(|a, b| b.count.cmp(&a.count).then(a.tag.name.cmp(&b.tag.name)));
+
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::articles::article::Source;
+
+    fn article(title: &str, tags: &[&str]) -> Article {
+        let tags: Vec<String> = tags.iter().map(|tag| tag.to_string()).collect();
+        Article {
+            title: title.to_string(),
+            url: format!("/articles/{}", title),
+            source: Source::Local,
+            pub_date: "2024/01/01".to_string(),
+            tags: Tag::from_names(&tags),
+            meta: serde_json::Value::Object(Default::default()),
+        }
+    }
+
+    #[test]
+    fn test_group_by_tag() {
+        let articles = vec![
+            article("a", &["Rust", "Go"]),
+            article("b", &["rust"]),
+            article("c", &["Go", "!!"]),
+            article("d", &["Docker"]),
+        ];
+        let groups = group_by_tag(&articles);
+
+        let names: Vec<(&str, usize)> = groups
+            .iter()
+            .map(|group| (group.tag.name.as_str(), group.count))
+            .collect();
+        assert_eq!(names, vec![("Go", 2), ("Rust", 2), ("Docker", 1)]);
+        assert_eq!(groups[1].tag.url, "/tags/rust");
+
+        // tags that would share a URL get pages of their own
+        let articles = vec![
+            article("a", &["C++"]),
+            article("b", &["C#"]),
+            article("c", &["c++", "Index"]),
+        ];
+        let groups = group_by_tag(&articles);
+        let slugs: Vec<&str> = groups.iter().map(|group| group.tag.slug.as_str()).collect();
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[0].tag.name, "C++");
+        assert_eq!(groups[0].count, 2);
+        assert!(slugs.iter().all(|slug| *slug != "c" && *slug != "index"));
+        assert!(slugs[0].starts_with("c-") && slugs[1].starts_with("c-"));
+        assert_ne!(slugs[0], slugs[1]);
+        assert_eq!(Tag::new("C#").slug, Tag::new("C#").slug);
+        assert_eq!(Tag::new("GitHub Actions").slug, "github-actions");
+    }
+}
@@ -0,0 +1,196 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use crate::cache;
+use crate::error::{Error, Result};
+
+// Assets
+//
+// With `[assets] minify`, generated HTML pages and the CSS and JS of the
+// statics are minified. With `[assets] fingerprint`, every static is also
+// written under a name with the hash of its contents (`style.3f9a1c2e.css`),
+// which a CDN can cache forever since any change gives it a new name.
+// Templates get that URL with `asset("style.css")`, and
+// `asset-manifest.json` in the output directory lists them all.
+
+pub const MANIFEST_FILE: &str = "asset-manifest.json";
+
+fn cfg() -> minify_html::Cfg {
+    minify_html::Cfg {
+        // the live reload script goes before </body>
+        keep_closing_tags: true,
+        keep_html_and_head_opening_tags: true,
+        minify_css: true,
+        minify_js: true,
+        ..minify_html::Cfg::default()
+    }
+}
+
+pub fn minify_html(html: &str) -> String {
+    String::from_utf8(minify_html::minify(html.as_bytes(), &cfg()))
+        .unwrap_or_else(|_| html.to_string())
+}
+
+/// Writes the generated page `content` to `path`, minified if it is HTML and
+/// `minify` is set.
+pub fn write_page(path: &Path, content: &str, minify: bool) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
+    }
+    let content = if minify && path.extension().is_some_and(|ext| ext == "html") {
+        minify_html(content)
+    } else {
+        content.to_string()
+    };
+    fs::write(path, content).map_err(|e| Error::io(path, e))
+}
+
+// minify-html only minifies CSS and JS inside HTML, so a file is minified as
+// the content of a `<style>` or `<script>` element.
+fn minify_embedded(tag: &str, code: &[u8]) -> Option<Vec<u8>> {
+    let (open, close) = (format!("<{}>", tag), format!("</{}>", tag));
+    let code = std::str::from_utf8(code).ok()?;
+    if code.contains(&close) {
+        return None;
+    }
+    let html = minify_html(&format!("{}{}{}", open, code, close));
+    let minified = html.strip_prefix(&open)?.strip_suffix(&close)?;
+    Some(minified.as_bytes().to_vec())
+}
+
+/// Minifies `contents` of the static `file` if it is CSS or JS that isn't
+/// minified already.
+pub fn minify(file: &Path, contents: Vec<u8>) -> Vec<u8> {
+    let name = file.to_string_lossy();
+    if name.ends_with(".min.css") || name.ends_with(".min.js") {
+        return contents;
+    }
+    let minified = match file.extension().and_then(|ext| ext.to_str()) {
+        Some("css") => minify_embedded("style", &contents),
+        Some("js") => minify_embedded("script", &contents),
+        _ => None,
+    };
+    minified.unwrap_or(contents)
+}
+
+/// `file` with the hash of `contents` before its extension.
+pub fn fingerprinted(file: &Path, contents: &[u8]) -> PathBuf {
+    let hash = cache::hash(&[contents]);
+    let stem = file
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let name = match file.extension() {
+        Some(ext) => format!("{}.{}.{}", stem, &hash[..8], ext.to_string_lossy()),
+        None => format!("{}.{}", stem, &hash[..8]),
+    };
+    file.with_file_name(name)
+}
+
+fn url(file: &Path) -> String {
+    format!("/statics/{}", file.to_string_lossy().replace('\\', "/"))
+}
+
+/// URLs of the statics by their names, which `asset()` looks up. Clones share
+/// the URLs, so they can be filled in once the statics are known.
+#[derive(Debug, Clone, Default)]
+pub struct Manifest(Arc<RwLock<BTreeMap<String, String>>>);
+
+impl Manifest {
+    /// Sets the URLs of `statics`, their file names and contents, fingerprinted
+    /// or not.
+    pub fn update(&self, statics: &BTreeMap<PathBuf, Vec<u8>>, fingerprint: bool) {
+        let urls = statics
+            .iter()
+            .map(|(file, contents)| {
+                let name = file.to_string_lossy().replace('\\', "/");
+                if fingerprint {
+                    (name, url(&fingerprinted(file, contents)))
+                } else {
+                    (name, url(file))
+                }
+            })
+            .collect();
+        *self.0.write().unwrap_or_else(|e| e.into_inner()) = urls;
+    }
+
+    /// The URL of the static `name`. Unknown names get their plain URL.
+    pub fn url(&self, name: &str) -> String {
+        let name = name.trim_start_matches('/');
+        let urls = self.0.read().unwrap_or_else(|e| e.into_inner());
+        urls.get(name)
+            .cloned()
+            .unwrap_or_else(|| url(Path::new(name)))
+    }
+
+    pub fn to_json(&self) -> String {
+        let urls = self.0.read().unwrap_or_else(|e| e.into_inner());
+        // string keys and values always serialize
+        serde_json::to_string_pretty(&*urls).unwrap_or_default()
+    }
+
+    /// Adds `asset(name)` to `env`, returning the URL of the static `name`.
+    pub fn add_function(&self, env: &mut minijinja::Environment<'static>) {
+        let manifest = self.clone();
+        env.add_function("asset", move |name: &str| manifest.url(name));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_minify() {
+        assert_eq!(
+            minify(
+                Path::new("style.css"),
+                b"body {\n  color: red;\n}\n".to_vec()
+            ),
+            b"body{color:red}"
+        );
+        let js = minify(
+            Path::new("main.js"),
+            b"function hello(name) {\n  console.log('hi ' + name);\n}\n".to_vec(),
+        );
+        assert!(js.len() < 50 && js.starts_with(b"function hello("));
+        for file in ["style.min.css", "a.png"] {
+            assert_eq!(minify(Path::new(file), b"a {  }".to_vec()), b"a {  }");
+        }
+
+        assert_eq!(
+            minify_html("<html>\n<body>\n  <p>a   b</p>\n  <pre>  c\n d</pre>\n</body>\n</html>\n"),
+            "<html><body><p>a b</p><pre>  c\n d</pre></body></html>"
+        );
+    }
+
+    #[test]
+    fn test_manifest() {
+        let statics = BTreeMap::from([
+            (PathBuf::from("style.css"), b"a".to_vec()),
+            (PathBuf::from("img/logo.png"), b"b".to_vec()),
+        ]);
+        let hash = &cache::hash(&[b"a"])[..8];
+        assert_eq!(
+            fingerprinted(Path::new("style.css"), b"a"),
+            PathBuf::from(format!("style.{}.css", hash))
+        );
+
+        let manifest = Manifest::default();
+        let mut env = minijinja::Environment::new();
+        manifest.add_function(&mut env);
+        manifest.update(&statics, true);
+        let render = |source| env.render_str(source, ()).unwrap();
+        assert_eq!(
+            render("{{ asset('style.css') }}"),
+            format!("/statics/style.{}.css", hash)
+        );
+        assert!(render("{{ asset('/img/logo.png') }}").starts_with("/statics/img/logo."));
+        assert_eq!(render("{{ asset('missing.js') }}"), "/statics/missing.js");
+
+        manifest.update(&statics, false);
+        assert_eq!(render("{{ asset('style.css') }}"), "/statics/style.css");
+    }
+}
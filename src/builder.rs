@@ -1,59 +1,190 @@
-use std::error::Error;
+use std::collections::BTreeMap;
 use std::fs;
-use std::io::Write;
 use std::path;
 
-use fs_extra::dir;
 use minijinja::context;
 
 use crate::articles::article;
+use crate::articles::external;
+use crate::articles::feed;
+use crate::articles::tag;
+use crate::assets;
+use crate::cache;
 use crate::config;
+use crate::error::{Error, Result};
+use crate::markdown;
+use crate::paginator;
+use crate::project;
+use crate::sitemap;
+use crate::templates;
+use crate::theme;
+
+#[derive(Debug, Clone)]
+pub struct BuildOptions {
+    pub root: path::PathBuf,           // プロジェクトのルート
+    pub config: Option<path::PathBuf>, // None のときは <root>/config.toml
+    pub force: bool,                   // ビルドキャッシュを無視してすべて生成し直す
+    pub drafts: bool,                  // 下書きと公開日前の記事も生成する
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        BuildOptions {
+            root: path::PathBuf::from("."),
+            config: None,
+            force: false,
+            drafts: false,
+        }
+    }
+}
 
 pub struct Builder {
     env: minijinja::Environment<'static>,
     config: config::Config,
     default_ctx: minijinja::Value,
     articles: article::Articles,
+    renderer: markdown::Renderer,
+    options: BuildOptions,
+    paths: project::Paths,
+    cache: cache::BuildCache,
+    manifest: assets::Manifest,
+    // hash of everything every page depends on (config, templates and statics' URLs)
+    deps_hash: String,
 }
 
-impl Builder {
-    pub async fn new() -> Result<Self, Box<dyn Error>> {
-        let mut env = minijinja::Environment::new();
-        #[cfg(feature = "bundled")]
-        {
-            minijinja_embed::load_templates!(&mut env);
+// Every static with the contents it is written with, by its path below
+// <output>/statics. Project statics replace the theme's files of the same name.
+fn collect_statics(
+    paths: &project::Paths,
+    renderer: &markdown::Renderer,
+    minify: bool,
+) -> Result<BTreeMap<path::PathBuf, Vec<u8>>> {
+    let mut files = BTreeMap::new();
+    for dir in paths.statics_dirs().iter().rev() {
+        let mut found = Vec::new();
+        cache::collect_files(dir, dir, &mut found)?;
+        for file in found {
+            files.insert(file.clone(), dir.join(file));
         }
-        #[cfg(not(feature = "bundled"))]
-        {
-            env.set_loader(minijinja::path_loader("./src/templates"));
+    }
+
+    let mut statics = BTreeMap::new();
+    // generated stylesheets, unless the project or theme ships its own
+    let highlight_css = path::PathBuf::from(markdown::highlight::CSS_FILE);
+    if !files.contains_key(&highlight_css) {
+        let css = renderer.highlighter().css().as_bytes().to_vec();
+        statics.insert(highlight_css, css);
+    }
+    for (file, src) in files {
+        let contents = fs::read(&src).map_err(|e| Error::io(&src, e))?;
+        statics.insert(file, contents);
+    }
+
+    if minify {
+        statics = statics
+            .into_iter()
+            .map(|(file, contents)| {
+                let contents = assets::minify(&file, contents);
+                (file, contents)
+            })
+            .collect();
+    }
+    Ok(statics)
+}
+
+impl Builder {
+    pub async fn new(options: BuildOptions) -> Result<Self> {
+        Builder::load(options, None).await
+    }
+
+    /// Reads the project again, e.g. after config.toml or a template changed.
+    /// The external feeds aren't fetched again unless the config lists others.
+    pub async fn reload(&self) -> Result<Self> {
+        Builder::load(self.options.clone(), Some(self)).await
+    }
+
+    async fn load(options: BuildOptions, previous: Option<&Builder>) -> Result<Self> {
+        let config_path = options
+            .config
+            .clone()
+            .unwrap_or_else(|| project::Paths::default_config(&options.root));
+        let (config, paths) = project::Paths::load(&options.root, config_path)?;
+
+        let mut config_files = vec![paths.config.clone()];
+        config_files.extend(paths.theme.as_ref().map(theme::Theme::config));
+        let mut deps = Vec::new();
+        for path in config_files.iter().filter(|path| path.exists()) {
+            deps.push(fs::read(path).map_err(|e| Error::io(path, e))?);
         }
+        deps.push(templates::hash(&paths.template_dirs())?.into_bytes());
 
-        let config_path = path::PathBuf::from("config.toml");
-        let config = config::from_file(config_path).unwrap();
+        // asset() is added before the environment is cloned; the URLs it
+        // returns are known once the renderer has made highlight.css
+        let manifest = assets::Manifest::default();
+        let mut env = templates::environment(&paths.template_dirs())?;
+        manifest.add_function(&mut env);
         let default_ctx = context! {
             title => config.title,
             description => config.description,
             header => config.header,
             footer => config.footer,
             google_analytics => config.google_analytics,
+            feeds => feed::links(&config.feeds, &config.title),
+        };
+
+        let renderer = markdown::Renderer::new(&config, env.clone(), &paths)?;
+        let statics = collect_statics(&paths, &renderer, config.assets.minify)?;
+        manifest.update(&statics, config.assets.fingerprint);
+        deps.push(manifest.to_json().into_bytes());
+        let deps: Vec<&[u8]> = deps.iter().map(|dep| dep.as_slice()).collect();
+        let deps_hash = cache::hash(&deps);
+
+        let links = &config.rss.external_rss_links;
+        let external_articles = match previous.filter(|b| &b.config.rss.external_rss_links == links)
+        {
+            Some(previous) => previous.articles.external_articles().clone(),
+            None => external::ExternalArticles::from_rss(links.clone()).await?,
         };
 
         /* 目印 */
         let articles = article::Articles::new(
-            config.rss.external_rss_links.clone(),
+            external_articles,
             env.clone(),
             default_ctx.clone(),
-        )
-        .await?;
+            renderer.clone(),
+            paths.clone(),
+            options.drafts,
+            config.assets.minify,
+        )?;
+
+        let cache = cache::BuildCache::load(paths.output.join(cache::CACHE_FILE), options.force);
 
         Ok(Builder {
             env,
             config,
             default_ctx,
             articles,
+            renderer,
+            options,
+            paths,
+            cache,
+            manifest,
+            deps_hash,
         })
     }
 
+    pub fn config(&self) -> &config::Config {
+        &self.config
+    }
+
+    pub fn options(&self) -> &BuildOptions {
+        &self.options
+    }
+
+    pub fn paths(&self) -> &project::Paths {
+        &self.paths
+    }
+
     fn context(&self, ctx: minijinja::Value) -> minijinja::Value {
         context! {
             ..ctx,
@@ -61,95 +192,226 @@ impl Builder {
         }
     }
 
-    fn build_template(
-        &self,
-        template_name: &str,
-        ctx: minijinja::Value,
-    ) -> Result<String, minijinja::Error> {
+    fn build_template(&self, template_name: &str, ctx: minijinja::Value) -> Result<String> {
         let template = self.env.get_template(template_name)?;
         let page = self.context(ctx);
-        template.render(context!(page))
+        Ok(template.render(context!(page))?)
     }
 
-    fn build_index(&self) -> Result<(), ()> {
-        let articles = self.articles.aggregate_articles().unwrap();
+    /// Writes `content` to `path` below the output directory.
+    fn save_page(&self, path: impl AsRef<path::Path>, content: &str) -> Result<()> {
+        let path = self.paths.output.join(path);
+        assets::write_page(&path, content, self.config.assets.minify)
+    }
 
-        let content = self
-            .build_template(
-                "index.html",
-                context! {
-                    profile => self.config.profile,
-                    articles => {
-                        let limit = articles.len().min(5);
-                        &articles[..limit]
-                    },
+    fn build_index(&self) -> Result<()> {
+        let articles = self.articles.aggregate_articles()?;
+
+        let content = self.build_template(
+            "index.html",
+            context! {
+                profile => self.config.profile,
+                articles => {
+                    let limit = articles.len().min(5);
+                    &articles[..limit]
                 },
-            )
-            .unwrap();
+            },
+        )?;
 
-        // save file
-        let mut file = fs::File::create("./generates/index.html").unwrap();
-        file.write_all(content.as_bytes()).unwrap();
-        Ok(())
+        self.save_page("index.html", &content)
     }
 
-    fn build_articles(&self) -> Result<(), ()> {
-        let articles = self.articles.aggregate_articles().unwrap();
+    fn build_articles(&self) -> Result<()> {
+        let articles = self.articles.aggregate_articles()?;
 
-        let content = self
-            .build_template(
+        // pages are renumbered whenever an article is added, so drop the old ones.
+        // only the numbered files: a page bundle named `page` shares the directory
+        let pages_dir = self.paths.output.join("articles/page");
+        if pages_dir.exists() {
+            for entry in fs::read_dir(&pages_dir).map_err(|e| Error::io(&pages_dir, e))? {
+                let path = entry.map_err(|e| Error::io(&pages_dir, e))?.path();
+                let numbered = path.extension().is_some_and(|ext| ext == "html")
+                    && path
+                        .file_stem()
+                        .and_then(|stem| stem.to_str())
+                        .is_some_and(|stem| stem.parse::<usize>().is_ok());
+                if numbered {
+                    fs::remove_file(&path).map_err(|e| Error::io(&path, e))?;
+                }
+            }
+        }
+
+        for page in paginator::paginate(&articles, self.config.pagination.per_page, "/articles") {
+            let content = self.build_template(
                 "articles.html",
                 context! {
-                    articles => articles,
+                    articles => page.items,
+                    ..minijinja::Value::from_serialize(&page.pagination),
                 },
-            )
-            .unwrap();
+            )?;
+            self.save_page(page.output_path(), &content)?;
+        }
 
-        // save file
-        let mut file = fs::File::create("./generates/articles.html").unwrap();
-        file.write_all(content.as_bytes()).unwrap();
         Ok(())
     }
 
-    fn build_about(&self) -> Result<(), ()> {
-        let content = self
-            .build_template(
-                "about.html",
-                context! {
-                    profile => self.config.profile,
-                    // articles => self.articles,
-                },
-            )
-            .unwrap();
+    fn build_about(&self) -> Result<()> {
+        let content = self.build_template(
+            "about.html",
+            context! {
+                profile => self.config.profile,
+                // articles => self.articles,
+            },
+        )?;
+
+        self.save_page("about.html", &content)
+    }
+
+    fn build_tags(&self) -> Result<()> {
+        let articles = self.articles.aggregate_articles()?;
+        let groups = tag::group_by_tag(&articles);
+
+        // tag pages are cheap to render, so start over instead of tracking removed tags
+        let tags_dir = self.paths.output.join("tags");
+        if tags_dir.exists() {
+            fs::remove_dir_all(&tags_dir).map_err(|e| Error::io(&tags_dir, e))?;
+        }
+
+        let content = self.build_template(
+            "tags.html",
+            context! {
+                tags => groups,
+            },
+        )?;
+        self.save_page("tags/index.html", &content)?;
+
+        for group in &groups {
+            let pages = paginator::paginate(
+                &group.articles,
+                self.config.pagination.per_page,
+                &group.tag.url,
+            );
+            for page in pages {
+                let content = self.build_template(
+                    "tag.html",
+                    context! {
+                        tag => group.tag,
+                        articles => page.items,
+                        ..minijinja::Value::from_serialize(&page.pagination),
+                    },
+                )?;
+                self.save_page(page.output_path(), &content)?;
+            }
+        }
 
-        // save file
-        let mut file = fs::File::create("./generates/about.html").unwrap();
-        file.write_all(content.as_bytes()).unwrap();
         Ok(())
     }
 
-    fn build_statics(&self) -> Result<(), ()> {
-        let gen_statics = path::Path::new("./generates/statics");
-        if gen_statics.exists() {
-            fs::remove_dir_all("./generates/statics").unwrap();
+    // Every page the builder generates, in the order they are built.
+    fn sitemap_entries(&self) -> Result<Vec<sitemap::Entry>> {
+        let articles = self.articles.aggregate_articles()?;
+        let per_page = self.config.pagination.per_page;
+
+        let mut entries = vec![sitemap::Entry::new("/")];
+        for article in self.articles.local_articles() {
+            entries.push(sitemap::Entry {
+                path: article.url_path(),
+                lastmod: Some(article.last_modified()),
+            });
         }
+        for page in paginator::paginate(&articles, per_page, "/articles") {
+            entries.push(sitemap::Entry::new(&page.url));
+        }
+        entries.push(sitemap::Entry::new("/tags"));
+        for group in tag::group_by_tag(&articles) {
+            for page in paginator::paginate(&group.articles, per_page, &group.tag.url) {
+                entries.push(sitemap::Entry::new(&page.url));
+            }
+        }
+        entries.push(sitemap::Entry::new("/about"));
+
+        Ok(entries)
+    }
+
+    fn build_sitemap(&self) -> Result<()> {
+        let base_url = url::Url::parse(&self.config.url).map_err(|source| Error::Url {
+            url: self.config.url.clone(),
+            source,
+        })?;
+
+        let xml = sitemap::sitemap(&base_url, &self.sitemap_entries()?)?;
+        self.save_page(sitemap::SITEMAP_FILE, &xml)?;
+
+        let txt = sitemap::robots(&base_url, &self.config.robots)?;
+        self.save_page(sitemap::ROBOTS_FILE, &txt)
+    }
+
+    // Writes `file` below <output>/statics unless it is unchanged since the last build.
+    fn save_static(&mut self, file: &path::Path, contents: &[u8]) -> Result<()> {
+        let output = path::Path::new("statics").join(file);
+        if self
+            .cache
+            .is_fresh(&output.to_string_lossy(), &cache::hash(&[contents]))
+        {
+            return Ok(());
+        }
+
+        let dest = self.paths.output.join(output);
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
+        }
+        fs::write(&dest, contents).map_err(|e| Error::io(&dest, e))
+    }
+
+    pub fn build_statics(&mut self) -> Result<()> {
+        let static_dest = self.paths.output.join("statics");
+        if self.options.force && static_dest.exists() {
+            fs::remove_dir_all(&static_dest).map_err(|e| Error::io(&static_dest, e))?;
+        }
+
+        let fingerprint = self.config.assets.fingerprint;
+        let statics = collect_statics(&self.paths, &self.renderer, self.config.assets.minify)?;
+        self.manifest.update(&statics, fingerprint);
+        for (file, contents) in &statics {
+            // the plain name is kept for links that don't go through asset()
+            self.save_static(file, contents)?;
+            if fingerprint {
+                self.save_static(&assets::fingerprinted(file, contents), contents)?;
+            }
+        }
+
+        for stale in self.cache.stale("statics/") {
+            let _ = fs::remove_file(self.paths.output.join(stale));
+        }
+        self.cache.save()?;
+
+        let manifest = self.paths.output.join(assets::MANIFEST_FILE);
+        fs::write(&manifest, self.manifest.to_json()).map_err(|e| Error::io(&manifest, e))
+    }
+
+    /// Re-reads local articles from disk, keeping the already fetched external articles.
+    pub fn reload_articles(&mut self) -> Result<()> {
+        self.articles.reload_local_articles()
+    }
 
-        let mut copy_options = dir::CopyOptions::new();
-        copy_options.overwrite = true;
-        let static_src = path::Path::new("./statics");
-        let static_dest = path::Path::new("./generates/");
-        dir::copy(static_src, static_dest, &copy_options).unwrap();
+    /// Builds every page without touching statics.
+    pub fn build_pages(&mut self) -> Result<()> {
+        self.build_index()?;
+        self.articles
+            .build_articles(&mut self.cache, &self.deps_hash)?;
+        self.articles.generate_feeds(&self.config)?;
+        self.build_articles()?;
+        self.build_tags()?;
+        self.build_about()?;
+        self.build_sitemap()?;
+        self.cache.save()?;
 
         Ok(())
     }
 
-    pub fn build(&self) -> Result<(), ()> {
-        self.build_index().unwrap();
-        self.articles.build_articles().unwrap();
-        self.articles.generate_rss(&self.config.rss).unwrap();
-        self.build_articles().unwrap();
-        self.build_about().unwrap();
-        self.build_statics().unwrap();
+    pub fn build(&mut self) -> Result<()> {
+        self.build_pages()?;
+        self.build_statics()?;
 
         Ok(())
     }
@@ -0,0 +1,225 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::error::{Error, Result};
+
+// Build Cache
+//
+// The manifest maps every generated file (relative to the output directory) to the
+// hash of the inputs it was rendered from. An output is only rewritten when
+// that hash changes or the file has gone missing.
+
+pub const CACHE_FILE: &str = ".overture-cache";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct Manifest {
+    version: String,
+    outputs: BTreeMap<String, String>,
+}
+
+pub struct BuildCache {
+    path: PathBuf,
+    force: bool,
+    previous: Manifest,
+    current: Manifest,
+}
+
+pub fn hash(inputs: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+    for input in inputs {
+        // length prefix keeps ("ab", "c") and ("a", "bc") apart
+        hasher.update((input.len() as u64).to_le_bytes());
+        hasher.update(input);
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Hashes every file below `dir`, including relative paths so renames count as changes.
+pub fn hash_dir(dir: &Path) -> Result<String> {
+    let mut files = Vec::new();
+    collect_files(dir, dir, &mut files)?;
+    files.sort();
+
+    let mut inputs = Vec::new();
+    for file in &files {
+        inputs.push(file.to_string_lossy().as_bytes().to_vec());
+        let path = dir.join(file);
+        inputs.push(fs::read(&path).map_err(|e| Error::io(&path, e))?);
+    }
+    let inputs: Vec<&[u8]> = inputs.iter().map(|input| input.as_slice()).collect();
+    Ok(hash(&inputs))
+}
+
+pub fn collect_files(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    if !dir.exists() {
+        return Ok(());
+    }
+    for entry in fs::read_dir(dir).map_err(|e| Error::io(dir, e))? {
+        let path = entry.map_err(|e| Error::io(dir, e))?.path();
+        if path.is_dir() {
+            collect_files(root, &path, files)?;
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            files.push(relative.to_path_buf());
+        }
+    }
+    Ok(())
+}
+
+/// Writes `contents` to `path` in a cache directory, creating it if needed.
+/// Errors are ignored: a cache entry that can't be written is only made
+/// again by the next build.
+pub fn write(path: &Path, contents: &str) {
+    if let Some(dir) = path.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    let _ = fs::write(path, contents);
+}
+
+/// Removes the files in `dir`, a cache named by hashes, whose name without
+/// extension isn't in `used`.
+pub fn prune(dir: &Path, used: &HashSet<String>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for path in entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+    {
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string());
+        if !stem.is_some_and(|stem| used.contains(&stem)) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+impl BuildCache {
+    /// Loads the manifest at `path`. With `force`, every output is treated as stale.
+    pub fn load(path: PathBuf, force: bool) -> Self {
+        let previous = fs::read_to_string(&path)
+            .ok()
+            .and_then(|contents| serde_json::from_str::<Manifest>(&contents).ok())
+            // a manifest written by another version may hash differently
+            .filter(|manifest| manifest.version == env!("CARGO_PKG_VERSION"))
+            .unwrap_or_default();
+
+        BuildCache {
+            path,
+            force,
+            previous,
+            current: Manifest {
+                version: env!("CARGO_PKG_VERSION").to_string(),
+                outputs: BTreeMap::new(),
+            },
+        }
+    }
+
+    /// Returns true if `output` was generated from inputs hashing to `hash` and still exists.
+    /// The output is recorded either way, so it is kept in the next manifest.
+    pub fn is_fresh(&mut self, output: &str, hash: &str) -> bool {
+        self.current
+            .outputs
+            .insert(output.to_string(), hash.to_string());
+        if self.force {
+            return false;
+        }
+
+        let output_path = self.path.parent().unwrap_or(Path::new("")).join(output);
+        self.previous.outputs.get(output).map(String::as_str) == Some(hash) && output_path.exists()
+    }
+
+    /// Records `output` as part of this build without checking it, for outputs
+    /// named after their inputs.
+    pub fn keep(&mut self, output: &str) {
+        self.current
+            .outputs
+            .insert(output.to_string(), String::new());
+    }
+
+    /// Outputs under `prefix` that were generated last time but not recorded in this build.
+    /// They are forgotten, so the caller is expected to delete them.
+    pub fn stale(&mut self, prefix: &str) -> Vec<String> {
+        let stale: Vec<String> = self
+            .previous
+            .outputs
+            .keys()
+            .filter(|output| output.starts_with(prefix))
+            .filter(|output| !self.current.outputs.contains_key(*output))
+            .cloned()
+            .collect();
+        for output in &stale {
+            self.previous.outputs.remove(output);
+        }
+        stale
+    }
+
+    /// Writes the manifest and starts a new build on top of it. Outputs that
+    /// were not visited in this build (e.g. statics on a pages-only rebuild) are kept.
+    pub fn save(&mut self) -> Result<()> {
+        let current = std::mem::take(&mut self.current.outputs);
+        self.previous.outputs.extend(current);
+        self.previous.version = env!("CARGO_PKG_VERSION").to_string();
+
+        let json = serde_json::to_string_pretty(&self.previous)
+            .map_err(|e| Error::io(&self.path, e.into()))?;
+        fs::write(&self.path, json).map_err(|e| Error::io(&self.path, e))?;
+
+        self.force = false;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_build_cache() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        fs::write(dir.join("a.html"), "a").unwrap();
+        fs::write(dir.join("b.html"), "b").unwrap();
+
+        let mut cache = BuildCache::load(dir.join(".overture-cache"), false);
+        assert!(!cache.is_fresh("a.html", "1"));
+        assert!(!cache.is_fresh("b.html", "1"));
+        cache.save().unwrap();
+
+        let mut cache = BuildCache::load(dir.join(".overture-cache"), false);
+        assert!(cache.is_fresh("a.html", "1"));
+        assert!(!cache.is_fresh("c.html", "1"));
+        assert_eq!(cache.stale(""), vec!["b.html".to_string()]);
+
+        let mut cache = BuildCache::load(dir.join(".overture-cache"), true);
+        assert!(!cache.is_fresh("a.html", "1"));
+    }
+
+    #[test]
+    fn test_prune() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        fs::write(dir.join("a.svg"), "a").unwrap();
+        fs::write(dir.join("b.svg"), "b").unwrap();
+
+        prune(dir, &HashSet::from(["a".to_string()]));
+        assert!(dir.join("a.svg").exists());
+        assert!(!dir.join("b.svg").exists());
+        // a cache that was never written is fine
+        prune(&dir.join("missing"), &HashSet::new());
+    }
+
+    #[test]
+    fn test_hash() {
+        assert_eq!(hash(&[b"ab", b"c"]), hash(&[b"ab", b"c"]));
+        assert_ne!(hash(&[b"ab", b"c"]), hash(&[b"a", b"bc"]));
+    }
+}
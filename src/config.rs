@@ -1,12 +1,17 @@
+use std::collections::BTreeMap;
 use std::fs;
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use toml;
 
+use crate::error::{Error, Location, Result};
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Config {
+    pub theme: Option<String>, // None のときは組み込みのテンプレート (default テーマ)
     pub title: String,
     pub description: String,
     pub url: String,
@@ -16,6 +21,24 @@ pub struct Config {
     pub footer: Footer,
     pub rss: Rss,
     pub google_analytics: Option<GoogleAnalytics>,
+    #[serde(default)]
+    pub pagination: Pagination,
+    #[serde(default)]
+    pub feeds: Feeds,
+    #[serde(default)]
+    pub robots: Robots,
+    #[serde(default)]
+    pub paths: Paths,
+    #[serde(default)]
+    pub highlight: Highlight,
+    #[serde(default)]
+    pub classes: Classes,
+    #[serde(default)]
+    pub markdown: Markdown,
+    #[serde(default)]
+    pub images: Images,
+    #[serde(default)]
+    pub assets: Assets,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -41,6 +64,8 @@ pub struct Rss {
     pub title: String,
     pub url: String,
     pub description: String,
+    pub language: Option<String>,
+    pub image: Option<String>, // チャンネル画像の URL
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -82,14 +107,318 @@ pub struct GoogleAnalytics {
     pub tracking_id: String,
 }
 
-pub fn from_file(path: PathBuf) -> Result<Config, toml::de::Error> {
-    let contents = fs::read_to_string(path).expect("Something went wrong reading the file");
-    toml::from_str(&contents)
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Pagination {
+    pub per_page: usize, // 0 のときはページ分割しない
+}
+
+impl Default for Pagination {
+    fn default() -> Self {
+        Pagination { per_page: 20 }
+    }
+}
+
+/// Project directories, relative to the project root.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Paths {
+    pub content: PathBuf,
+    pub output: PathBuf,
+    pub statics: PathBuf,
+    pub templates: PathBuf, // 組み込みのテンプレートを上書きするファイルを置く
+    pub themes: PathBuf,
+    pub cache: PathBuf, // 図などの生成物。出力には含めない
+}
+
+impl Default for Paths {
+    fn default() -> Self {
+        Paths {
+            content: PathBuf::from("articles"),
+            output: PathBuf::from("generates"),
+            statics: PathBuf::from("statics"),
+            templates: PathBuf::from("templates"),
+            themes: PathBuf::from("themes"),
+            cache: PathBuf::from(".cache"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Highlight {
+    pub theme: String, // syntect に組み込まれているテーマの名前
+}
+
+impl Default for Highlight {
+    fn default() -> Self {
+        Highlight {
+            theme: "InspiredGitHub".to_string(),
+        }
+    }
+}
+
+/// Markdown extensions. `math` applies to every article unless its front
+/// matter says otherwise.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Markdown {
+    pub footnotes: bool,
+    pub math: bool, // $...$ と $$...$$ を MathML にする
+    pub definition_lists: bool,
+    pub smart_punctuation: bool, // "..." を “...” に、-- を – にするなど
+    pub raw_html: bool,          // false のときは HTML をエスケープする
+    pub diagrams: BTreeMap<String, Diagram>, // コードブロックの言語ごと
+}
+
+impl Default for Markdown {
+    fn default() -> Self {
+        Markdown {
+            footnotes: true,
+            math: false,
+            definition_lists: false,
+            smart_punctuation: false,
+            raw_html: false,
+            diagrams: BTreeMap::new(),
+        }
+    }
+}
+
+/// How code blocks of a diagram language are rendered.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Diagram {
+    /// A command that reads the diagram on stdin and writes SVG to stdout.
+    /// Without one, the diagram is left for JavaScript to render.
+    pub command: Vec<String>,
+}
+
+/// Resized and re-encoded variants of the images in articles. Encoding AVIF
+/// is slow, so they are only made for sites with an `[images]` section.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Images {
+    #[serde(default = "images_enabled")]
+    pub enabled: bool, // [images] があれば true
+    pub widths: Vec<u32>, // 元の画像より小さいものだけ作る
+    pub formats: Vec<ImageFormat>,
+    pub quality: u8, // AVIF の品質 (1-100)
+}
+
+fn images_enabled() -> bool {
+    true
+}
+
+impl Default for Images {
+    fn default() -> Self {
+        Images {
+            enabled: false,
+            widths: vec![480, 960, 1440],
+            // lossless WebP is larger than most photos, so it is left to screenshots
+            formats: vec![ImageFormat::Avif],
+            quality: 70,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageFormat {
+    Avif,
+    Webp, // 可逆圧縮
+}
+
+/// Minification of the generated site and fingerprinting of its statics.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Assets {
+    pub minify: bool,      // HTML のページと statics の CSS と JS
+    pub fingerprint: bool, // statics を内容のハッシュ付きの名前でも書き出す
+}
+
+impl Default for Assets {
+    fn default() -> Self {
+        Assets {
+            minify: true,
+            fingerprint: true,
+        }
+    }
+}
+
+/// Classes added to the elements of rendered articles, by element name.
+/// Entries are merged over the defaults; an empty value removes one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "BTreeMap<String, String>", into = "BTreeMap<String, String>")]
+pub struct Classes(BTreeMap<String, String>);
+
+impl Classes {
+    /// Classes without the defaults.
+    pub fn new(classes: BTreeMap<String, String>) -> Self {
+        Classes(classes)
+    }
+
+    pub fn get(&self, element: &str) -> Option<&str> {
+        self.0.get(element).map(String::as_str)
+    }
+}
+
+impl Default for Classes {
+    // 組み込みのテンプレートが使う Tailwind のクラス
+    fn default() -> Self {
+        let classes = [
+            ("h1", "text-xl font-bold py-2"),
+            ("h2", "text-lg font-bold py-2"),
+            ("h3", "text-base font-bold py-2"),
+            ("h4", "text-sm font-bold py-2"),
+            ("p", "text-sm text-gray-600 py-2"),
+            ("a", "text-sm text-blue-600 hover:text-blue-800"),
+            ("ul", "list-disc list-outside pl-4"),
+            ("ol", "list-decimal list-outside pl-4"),
+            ("li", "text-sm text-gray-600 pt-1"),
+            ("pre", "p-4 text-sm overflow-x-auto"),
+            (
+                "blockquote",
+                "text-sm italic font-semibold text-gray-600 border-l-4 border-gray-400 pl-3 py-2",
+            ),
+            (
+                "table",
+                "w-full text-sm text-left rtl:text-right text-gray-500 py-2",
+            ),
+            ("thead", "text-xs text-gray-700 uppercase bg-gray-50"),
+            ("th", "px-6 py-3 text-xs"),
+            ("td", "px-6 py-3 text-xs"),
+            ("tr", "border-b"),
+            ("dt", "text-sm font-bold pt-2"),
+            ("dd", "text-sm text-gray-600 pl-4"),
+        ];
+        Classes(
+            classes
+                .into_iter()
+                .map(|(element, class)| (element.to_string(), class.to_string()))
+                .collect(),
+        )
+    }
+}
+
+impl From<BTreeMap<String, String>> for Classes {
+    fn from(overrides: BTreeMap<String, String>) -> Self {
+        let mut classes = Classes::default().0;
+        classes.extend(overrides);
+        classes.retain(|_, class| !class.is_empty());
+        Classes(classes)
+    }
+}
+
+impl From<Classes> for BTreeMap<String, String> {
+    fn from(classes: Classes) -> Self {
+        classes.0
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Robots {
+    pub user_agent: String,
+    pub allow: Vec<String>,
+    pub disallow: Vec<String>,
+}
+
+impl Default for Robots {
+    fn default() -> Self {
+        Robots {
+            user_agent: "*".to_string(),
+            allow: Vec::new(),
+            disallow: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FeedContent {
+    Summary, // front matter の description のみ
+    Full,    // 記事の HTML 全体
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Feeds {
+    pub rss: bool,
+    pub atom: bool,
+    pub json: bool,
+    pub content: FeedContent,
+}
+
+impl Default for Feeds {
+    fn default() -> Self {
+        Feeds {
+            rss: true,
+            atom: false,
+            json: false,
+            content: FeedContent::Summary,
+        }
+    }
+}
+
+/// The part of config.toml needed to find the theme, before the theme's
+/// defaults can be applied.
+#[derive(Debug, Default, Deserialize)]
+pub struct ThemeSelection {
+    pub theme: Option<String>,
+    #[serde(default)]
+    pub paths: Paths,
+}
+
+fn read_toml<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let contents = fs::read_to_string(path).map_err(|e| Error::io(path, e))?;
+    toml::from_str(&contents).map_err(|e| Error::Config {
+        location: e
+            .span()
+            .map(|span| Location::from_offset(&contents, span.start, 1)),
+        message: e.message().to_string(),
+        path: path.to_path_buf(),
+    })
+}
+
+// Merges `overrides` into `base`, table by table.
+fn merge(base: &mut toml::Table, overrides: toml::Table) {
+    for (key, value) in overrides {
+        match (base.get_mut(&key), value) {
+            (Some(toml::Value::Table(base)), toml::Value::Table(value)) => merge(base, value),
+            (Some(slot), value) => *slot = value,
+            (None, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+pub fn from_file(path: PathBuf) -> Result<Config> {
+    read_toml(&path)
+}
+
+pub fn theme_selection(path: &Path) -> Result<ThemeSelection> {
+    read_toml(path)
+}
+
+/// Like `from_file`, with anything `path` leaves out taken from `defaults`.
+pub fn from_file_with_defaults(path: PathBuf, defaults: &Path) -> Result<Config> {
+    let mut table: toml::Table = read_toml(defaults)?;
+    merge(&mut table, read_toml(&path)?);
+    // the merged table has no spans left to point at
+    toml::Value::Table(table)
+        .try_into()
+        .map_err(|e: toml::de::Error| Error::Config {
+            path,
+            location: None,
+            message: e.message().to_string(),
+        })
 }
 
 impl Default for Config {
     fn default() -> Self {
         Config {
+            theme: None,
             title: "Default Title".to_string(),
             description: "Default Description".to_string(),
             url: "https://example.com".to_string(),
@@ -108,6 +437,10 @@ impl Default for Config {
                         name: "Articles".to_string(),
                         url: "/articles".to_string(),
                     },
+                    HeaderLink {
+                        name: "Tags".to_string(),
+                        url: "/tags".to_string(),
+                    },
                 ],
             },
             profile: Profile {
@@ -144,6 +477,8 @@ impl Default for Config {
                 title: "Default RSS Title".to_string(),
                 description: "Default RSS Description".to_string(),
                 url: "https://example.com/rss".to_string(),
+                language: Some("ja".to_string()),
+                image: None,
                 external_rss_links: vec![
                     "https://zenn.dev/ucpr/feed?include_scraps=1".to_string(),
                     "https://ucpr.hatenablog.com/rss".to_string(),
@@ -152,16 +487,88 @@ impl Default for Config {
             google_analytics: Some(GoogleAnalytics {
                 tracking_id: "UA-123456789-0".to_string(),
             }),
+            pagination: Pagination::default(),
+            feeds: Feeds::default(),
+            robots: Robots::default(),
+            paths: Paths::default(),
+            highlight: Highlight::default(),
+            classes: Classes::default(),
+            markdown: Markdown::default(),
+            images: Images::default(),
+            assets: Assets::default(),
         }
     }
 }
 
 impl Config {
-    pub fn to_file(&self, path: PathBuf) -> Result<(), ()> {
-        let toml = toml::to_string(self).unwrap();
-        let mut file = fs::File::create(path).unwrap();
-        file.write_all(toml.as_bytes()).unwrap();
+    pub fn to_file(&self, path: PathBuf) -> Result<()> {
+        let toml = toml::to_string(self).map_err(|e| Error::Config {
+            path: path.clone(),
+            location: None,
+            message: e.to_string(),
+        })?;
+        let mut file = fs::File::create(&path).map_err(|e| Error::io(&path, e))?;
+        file.write_all(toml.as_bytes())
+            .map_err(|e| Error::io(&path, e))?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge() {
+        let mut base: toml::Table = toml::from_str(
+            "title = \"theme\"\n[pagination]\nper_page = 10\n[feeds]\natom = true\n",
+        )
+        .unwrap();
+        let overrides: toml::Table =
+            toml::from_str("title = \"site\"\n[pagination]\nper_page = 5\n").unwrap();
+        merge(&mut base, overrides);
+
+        assert_eq!(base["title"].as_str(), Some("site"));
+        assert_eq!(base["pagination"]["per_page"].as_integer(), Some(5));
+        assert_eq!(base["feeds"]["atom"].as_bool(), Some(true));
+    }
+
+    #[test]
+    fn test_classes() {
+        let classes: Classes = toml::from_str::<BTreeMap<String, String>>(
+            "h1 = \"title\"\ncode = \"mono\"\ntr = \"\"\n",
+        )
+        .unwrap()
+        .into();
+
+        assert_eq!(classes.get("h1"), Some("title"));
+        assert_eq!(classes.get("code"), Some("mono"));
+        assert_eq!(classes.get("tr"), None);
+        assert_eq!(classes.get("h2"), Classes::default().get("h2"));
+    }
+
+    #[test]
+    fn test_minimal_theme() {
+        let example = Path::new(env!("CARGO_MANIFEST_DIR")).join("example");
+        let config = from_file_with_defaults(
+            example.join("config.toml"),
+            &example.join("themes/minimal/theme.toml"),
+        )
+        .unwrap();
+        // the theme's stylesheet has none of the default classes
+        for element in Classes::default().0.keys() {
+            assert_eq!(config.classes.get(element), None);
+        }
+    }
+
+    #[test]
+    fn test_images() {
+        assert!(!Images::default().enabled);
+        let images: Images = toml::from_str("widths = [640]\n").unwrap();
+        assert!(images.enabled);
+        assert_eq!(images.widths, [640]);
+        let images: Images = toml::from_str("enabled = false\n").unwrap();
+        assert!(!images.enabled);
+    }
+}
@@ -0,0 +1,172 @@
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A position in a source file. Lines and columns start at 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Location {
+    pub line: usize,
+    pub column: Option<usize>,
+}
+
+impl Location {
+    pub fn line(line: usize) -> Self {
+        Location { line, column: None }
+    }
+
+    /// Location of the byte `offset` in `text`, where `text` starts at line `first_line`.
+    pub fn from_offset(text: &str, offset: usize, first_line: usize) -> Self {
+        let before = &text[..offset.min(text.len())];
+        let line = first_line + before.matches('\n').count();
+        let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+        Location {
+            line,
+            column: Some(column),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a file failed.
+    Io { path: PathBuf, source: io::Error },
+    /// `config.toml` could not be parsed.
+    Config {
+        path: PathBuf,
+        location: Option<Location>,
+        message: String,
+    },
+    /// The front matter of an article is missing or invalid.
+    FrontMatter {
+        path: PathBuf,
+        location: Option<Location>,
+        message: String,
+    },
+    /// The markdown of an article could not be rendered.
+    Markdown {
+        path: PathBuf,
+        location: Option<Location>,
+        message: String,
+    },
+    /// A template could not be loaded or rendered.
+    Template(minijinja::Error),
+    /// An external RSS feed could not be fetched or parsed.
+    Rss { url: String, message: String },
+    /// A URL from the config is invalid.
+    Url {
+        url: String,
+        source: url::ParseError,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub fn io(path: impl AsRef<Path>, source: io::Error) -> Self {
+        Error::Io {
+            path: path.as_ref().to_path_buf(),
+            source,
+        }
+    }
+}
+
+fn fmt_location(
+    f: &mut fmt::Formatter<'_>,
+    path: &Path,
+    location: &Option<Location>,
+) -> fmt::Result {
+    write!(f, "{}", path.display())?;
+    if let Some(location) = location {
+        write!(f, ":{}", location.line)?;
+        if let Some(column) = location.column {
+            write!(f, ":{}", column)?;
+        }
+    }
+    Ok(())
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            Error::Config {
+                path,
+                location,
+                message,
+            }
+            | Error::FrontMatter {
+                path,
+                location,
+                message,
+            }
+            | Error::Markdown {
+                path,
+                location,
+                message,
+            } => {
+                fmt_location(f, path, location)?;
+                write!(f, ": {}", message)
+            }
+            Error::Template(source) => {
+                if let Some(name) = source.name() {
+                    let location = source.line().map(Location::line);
+                    fmt_location(f, Path::new(name), &location)?;
+                    write!(f, ": ")?;
+                }
+                match source.detail() {
+                    Some(detail) => write!(f, "{}: {}", source.kind(), detail),
+                    None => write!(f, "{}", source.kind()),
+                }
+            }
+            Error::Rss { url, message } => write!(f, "{}: {}", url, message),
+            Error::Url { url, source } => write!(f, "invalid url {:?}: {}", url, source),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Template(source) => Some(source),
+            Error::Url { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+impl From<minijinja::Error> for Error {
+    fn from(source: minijinja::Error) -> Self {
+        Error::Template(source)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_location_from_offset() {
+        let text = "title = \"a\"\ndate = \"x\"\n";
+        assert_eq!(
+            Location::from_offset(text, 19, 2),
+            Location {
+                line: 3,
+                column: Some(8)
+            }
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let err = Error::FrontMatter {
+            path: PathBuf::from("articles/foo.md"),
+            location: Some(Location::line(3)),
+            message: "invalid date \"2024-13-01\"".to_string(),
+        };
+        assert_eq!(
+            err.to_string(),
+            "articles/foo.md:3: invalid date \"2024-13-01\""
+        );
+    }
+}
@@ -1,5 +1,17 @@
 pub mod articles;
+pub mod assets;
 pub mod builder;
+pub mod cache;
 pub mod config;
+pub mod error;
+pub mod markdown;
+pub mod paginator;
 pub mod project;
 pub mod server;
+pub mod sitemap;
+pub mod slug;
+pub mod templates;
+pub mod theme;
+pub mod watcher;
+
+pub use error::{Error, Result};
@@ -1,80 +1,178 @@
 use clap::{Parser, Subcommand};
 use std::path::PathBuf;
-use tokio;
+use std::process::ExitCode;
+use tokio::sync::broadcast;
 
 use overture::builder;
 use overture::project;
 use overture::server;
+use overture::templates;
+use overture::watcher;
 
 #[derive(Debug, Parser)]
 struct Cli {
     #[command(subcommand)]
     command: Commands,
+
+    #[arg(short, long, global = true, default_value = ".", help = "Project root")]
+    root: PathBuf,
+
+    #[arg(
+        short,
+        long,
+        global = true,
+        help = "Config file [default: <root>/config.toml]"
+    )]
+    config: Option<PathBuf>,
 }
 
 #[derive(Debug, Subcommand)]
 enum Commands {
     #[command(about = "help for build")]
-    Build,
+    Build {
+        #[arg(short, long, help = "Ignore the build cache and regenerate everything")]
+        force: bool,
+
+        #[arg(short, long, help = "Include drafts and future-dated articles")]
+        drafts: bool,
+    },
 
     #[command(about = "help for serve")]
     Serve {
         #[arg(short, long)]
         port: u16,
+
+        #[arg(short, long, help = "Rebuild and reload the browser when files change")]
+        watch: bool,
+
+        #[arg(short, long, help = "Include drafts and future-dated articles")]
+        drafts: bool,
     },
 
     #[command(about = "help for init")]
-    Init {
-        #[arg(short, long)]
-        root: String,
+    Init,
+
+    #[command(about = "Copy the built-in templates into the project for customisation")]
+    EjectTemplates {
+        #[arg(
+            short,
+            long,
+            help = "Overwrite templates that already exist in the project"
+        )]
+        force: bool,
     },
 }
 
+fn eject_templates(
+    root: PathBuf,
+    config: Option<PathBuf>,
+    force: bool,
+) -> overture::Result<Vec<PathBuf>> {
+    let config_path = config.unwrap_or_else(|| project::Paths::default_config(&root));
+    let (_, paths) = project::Paths::load(&root, config_path)?;
+    // eject what the site renders with, including the theme's templates
+    let template_dirs = paths.template_dirs();
+    templates::eject(&paths.templates, &template_dirs[1..], force)
+}
+
 #[tokio::main]
-async fn main() {
+async fn main() -> ExitCode {
     let cli = Cli::parse();
     match cli.command {
-        Commands::Build => {
-            let builder = match builder::Builder::new().await {
+        Commands::Build { force, drafts } => {
+            let options = builder::BuildOptions {
+                root: cli.root,
+                config: cli.config,
+                force,
+                drafts,
+            };
+            let mut builder = match builder::Builder::new(options).await {
                 Ok(b) => b,
-                Err(_) => {
-                    println!("Error creating builder");
-                    return;
+                Err(e) => {
+                    eprintln!("Error creating builder: {}", e);
+                    return ExitCode::FAILURE;
                 }
             };
 
             match builder.build() {
                 Ok(_) => println!("Build successful"),
-                Err(_) => println!("Error building project"),
+                Err(e) => {
+                    eprintln!("Error building project: {}", e);
+                    return ExitCode::FAILURE;
+                }
             }
         }
 
-        Commands::Serve { port } => {
-            let builder = match builder::Builder::new().await {
+        Commands::Serve {
+            port,
+            watch,
+            drafts,
+        } => {
+            let options = builder::BuildOptions {
+                root: cli.root,
+                config: cli.config,
+                force: false,
+                drafts,
+            };
+            let mut builder = match builder::Builder::new(options).await {
                 Ok(b) => b,
-                Err(_) => {
-                    println!("Error creating builder");
-                    return;
+                Err(e) => {
+                    eprintln!("Error creating builder: {}", e);
+                    return ExitCode::FAILURE;
                 }
             };
 
-            match builder.build() {
-                Ok(_) => {
-                    let server = server::Server::new("127.0.0.1".to_string(), port);
-                    server.serve().await
+            if let Err(e) = builder.build() {
+                eprintln!("Error building project: {}", e);
+                return ExitCode::FAILURE;
+            }
+
+            let server =
+                server::Server::new("127.0.0.1".to_string(), port, &builder.paths().output);
+            if !watch {
+                server.serve().await;
+                return ExitCode::SUCCESS;
+            }
+
+            let (reload, _) = broadcast::channel(16);
+            let server = server.with_live_reload(reload.clone());
+            let watcher = watcher::Watcher::new(reload, builder.paths());
+            tokio::select! {
+                _ = server.serve() => {}
+                res = watcher.watch(builder) => {
+                    if let Err(e) = res {
+                        eprintln!("Error watching project: {}", e);
+                        return ExitCode::FAILURE;
+                    }
                 }
-                Err(_) => println!("Error building project"),
             }
         }
 
-        Commands::Init { root } => {
-            let root_path_buf = PathBuf::from(root);
-            let prj = project::Project::new(root_path_buf);
+        Commands::Init => {
+            let prj = project::Project::new(cli.root);
 
             match prj.create() {
                 Ok(_) => println!("Project created successfully"),
-                Err(e) => println!("Error creating project: {}", e),
+                Err(e) => {
+                    eprintln!("Error creating project: {}", e);
+                    return ExitCode::FAILURE;
+                }
             }
         }
+
+        Commands::EjectTemplates { force } => match eject_templates(cli.root, cli.config, force) {
+            Ok(written) => {
+                for path in &written {
+                    println!("Created {}", path.display());
+                }
+                println!("Ejected {} templates", written.len());
+            }
+            Err(e) => {
+                eprintln!("Error ejecting templates: {}", e);
+                return ExitCode::FAILURE;
+            }
+        },
     }
+
+    ExitCode::SUCCESS
 }
@@ -0,0 +1,150 @@
+use markdown::mdast::Node;
+
+use super::ast;
+
+// Alerts
+//
+// GitHub-style alerts are blockquotes whose first line is only a marker:
+//
+// > [!NOTE]
+// > Useful information.
+//
+// They are rendered as `<div class="alert alert-note">` with a title and an
+// Octicons icon instead of a blockquote.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Note,      // 補足
+    Tip,       // ヒント
+    Important, // 重要な情報
+    Warning,   // 注意が必要
+    Caution,   // 危険
+}
+
+impl Kind {
+    fn from_marker(marker: &str) -> Option<Kind> {
+        let name = marker.strip_prefix("[!")?.strip_suffix(']')?;
+        match name.to_ascii_lowercase().as_str() {
+            "note" => Some(Kind::Note),
+            "tip" => Some(Kind::Tip),
+            "important" => Some(Kind::Important),
+            "warning" => Some(Kind::Warning),
+            "caution" => Some(Kind::Caution),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Kind::Note => "note",
+            Kind::Tip => "tip",
+            Kind::Important => "important",
+            Kind::Warning => "warning",
+            Kind::Caution => "caution",
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            Kind::Note => "Note",
+            Kind::Tip => "Tip",
+            Kind::Important => "Important",
+            Kind::Warning => "Warning",
+            Kind::Caution => "Caution",
+        }
+    }
+
+    // 16px Octicons: info, light-bulb, report, alert and stop
+    fn icon(self) -> &'static str {
+        match self {
+            Kind::Note => "M0 8a8 8 0 1 1 16 0A8 8 0 0 1 0 8Zm8-6.5a6.5 6.5 0 1 0 0 13 6.5 6.5 0 0 0 0-13ZM6.5 7.75A.75.75 0 0 1 7.25 7h1a.75.75 0 0 1 .75.75v2.75h.25a.75.75 0 0 1 0 1.5h-2a.75.75 0 0 1 0-1.5h.25v-2h-.25a.75.75 0 0 1-.75-.75ZM8 6a1 1 0 1 1 0-2 1 1 0 0 1 0 2Z",
+            Kind::Tip => "M8 1.5c-2.363 0-4 1.69-4 3.75 0 .984.424 1.625.984 2.304l.214.253c.223.264.47.556.673.848.284.411.537.896.621 1.49a.75.75 0 0 1-1.484.211c-.04-.282-.163-.547-.37-.847a8.456 8.456 0 0 0-.542-.68c-.084-.1-.173-.205-.268-.32C3.201 7.75 2.5 6.766 2.5 5.25 2.5 2.31 4.863 0 8 0s5.5 2.31 5.5 5.25c0 1.516-.701 2.5-1.328 3.259-.095.115-.184.22-.268.319-.207.245-.383.453-.541.681-.208.3-.33.565-.37.847a.751.751 0 0 1-1.485-.212c.084-.593.337-1.078.621-1.489.203-.292.45-.584.673-.848.075-.088.147-.173.213-.253.561-.679.985-1.32.985-2.304 0-2.06-1.637-3.75-4-3.75ZM5.75 12h4.5a.75.75 0 0 1 0 1.5h-4.5a.75.75 0 0 1 0-1.5ZM6 15.25a.75.75 0 0 1 .75-.75h2.5a.75.75 0 0 1 0 1.5h-2.5a.75.75 0 0 1-.75-.75Z",
+            Kind::Important => "M0 1.75C0 .784.784 0 1.75 0h12.5C15.216 0 16 .784 16 1.75v9.5A1.75 1.75 0 0 1 14.25 13H8.06l-2.573 2.573A1.458 1.458 0 0 1 3 14.543V13H1.75A1.75 1.75 0 0 1 0 11.25Zm1.75-.25a.25.25 0 0 0-.25.25v9.5c0 .138.112.25.25.25h2a.75.75 0 0 1 .75.75v2.19l2.72-2.72a.749.749 0 0 1 .53-.22h6.5a.25.25 0 0 0 .25-.25v-9.5a.25.25 0 0 0-.25-.25Zm7 2.25v2.5a.75.75 0 0 1-1.5 0v-2.5a.75.75 0 0 1 1.5 0ZM9 9a1 1 0 1 1-2 0 1 1 0 0 1 2 0Z",
+            Kind::Warning => "M6.457 1.047c.659-1.234 2.427-1.234 3.086 0l6.082 11.378A1.75 1.75 0 0 1 14.082 15H1.918a1.75 1.75 0 0 1-1.543-2.575Zm1.763.707a.25.25 0 0 0-.44 0L1.698 13.132a.25.25 0 0 0 .22.368h12.164a.25.25 0 0 0 .22-.368Zm.53 3.996v2.5a.75.75 0 0 1-1.5 0v-2.5a.75.75 0 0 1 1.5 0ZM9 11a1 1 0 1 1-2 0 1 1 0 0 1 2 0Z",
+            Kind::Caution => "M4.47.22A.749.749 0 0 1 5 0h6c.199 0 .389.079.53.22l4.25 4.25c.141.14.22.331.22.53v6a.749.749 0 0 1-.22.53l-4.25 4.25A.749.749 0 0 1 11 16H5a.749.749 0 0 1-.53-.22L.22 11.53A.749.749 0 0 1 0 11V5c0-.199.079-.389.22-.53Zm.84 1.28L1.5 5.31v5.38l3.81 3.81h5.38l3.81-3.81V5.31L10.69 1.5ZM8 4a.75.75 0 0 1 .75.75v3.5a.75.75 0 0 1-1.5 0v-3.5A.75.75 0 0 1 8 4Zm0 8a1 1 0 1 1 0-2 1 1 0 0 1 0 2Z",
+        }
+    }
+}
+
+// Removes the marker from the blockquote's first line and returns its kind.
+fn take_marker(children: &mut Vec<Node>) -> Option<Kind> {
+    let Some(Node::Paragraph(paragraph)) = children.first_mut() else {
+        return None;
+    };
+    let Some(Node::Text(text)) = paragraph.children.first_mut() else {
+        return None;
+    };
+    // the marker has to be alone on its line
+    let (marker, rest) = text.value.split_once('\n').unwrap_or((&text.value, ""));
+    let kind = Kind::from_marker(marker.trim_end())?;
+
+    text.value = rest.to_string();
+    if text.value.is_empty() {
+        paragraph.children.remove(0);
+    }
+    if paragraph.children.is_empty() {
+        children.remove(0);
+    }
+    Some(kind)
+}
+
+fn alert(kind: Kind, children: Vec<Node>) -> Node {
+    let icon = ast::text_element(
+        "svg",
+        &[
+            ("class", "alert-icon"),
+            ("viewBox", "0 0 16 16"),
+            ("width", "16"),
+            ("height", "16"),
+            ("aria-hidden", "true"),
+        ],
+        vec![ast::text_element("path", &[("d", kind.icon())], Vec::new())],
+    );
+    let title = ast::flow_element(
+        "p",
+        &[("class", "alert-title")],
+        vec![icon, ast::text(kind.title())],
+    );
+
+    let mut body = vec![title];
+    body.extend(children);
+    let class = format!("alert alert-{}", kind.name());
+    ast::flow_element("div", &[("class", &class)], body)
+}
+
+/// Replaces alert blockquotes below `root` with alert elements.
+pub fn transform(root: &mut Node) {
+    ast::walk_mut(root, &mut |node| {
+        let Node::Blockquote(quote) = node else {
+            return;
+        };
+        if let Some(kind) = take_marker(&mut quote.children) {
+            *node = alert(kind, std::mem::take(&mut quote.children));
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markdown::render_transformed;
+
+    fn render(source: &str) -> String {
+        render_transformed(&transform, source).unwrap()
+    }
+
+    #[test]
+    fn test_transform() {
+        let html = render("> [!NOTE]\n> `NOTE` の例\n");
+        assert!(html.starts_with(
+            "<div class=\"alert alert-note\">\n<p class=\"alert-title\"><svg class=\"alert-icon\""
+        ));
+        assert!(html.contains("</svg>Note</p>\n<p><code>NOTE</code> の例</p>\n</div>"));
+
+        assert!(render("> [!warning]\n> a\n").contains("alert-warning"));
+
+        // the marker has to be on its own line, and known
+        assert!(render("> [!TIP] a\n").starts_with("<blockquote>"));
+        assert!(render("> [!TODO]\n> a\n").starts_with("<blockquote>"));
+    }
+}
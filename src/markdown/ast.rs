@@ -0,0 +1,78 @@
+use markdown::mdast::{
+    AttributeContent, AttributeValue, MdxFlowExpression, MdxJsxAttribute, MdxJsxFlowElement,
+    MdxJsxTextElement, MdxTextExpression, Node, Text,
+};
+
+// AST
+//
+// Transforms replace nodes with plain HTML elements. MDX is never parsed, so
+// its element nodes are free to carry them and `html::Writer` renders them as
+// `<name attributes>children</name>`, or `<name attributes />` for void
+// elements like `<img>`. Its expression nodes carry HTML that is output as is.
+
+fn attributes(attributes: &[(&str, &str)]) -> Vec<AttributeContent> {
+    attributes
+        .iter()
+        .map(|(name, value)| {
+            AttributeContent::Property(MdxJsxAttribute {
+                name: name.to_string(),
+                value: Some(AttributeValue::Literal(value.to_string())),
+            })
+        })
+        .collect()
+}
+
+/// A block element, e.g. `<div>`.
+pub fn flow_element(name: &str, attrs: &[(&str, &str)], children: Vec<Node>) -> Node {
+    Node::MdxJsxFlowElement(MdxJsxFlowElement {
+        children,
+        position: None,
+        name: Some(name.to_string()),
+        attributes: attributes(attrs),
+    })
+}
+
+/// An inline element, e.g. `<span>`.
+pub fn text_element(name: &str, attrs: &[(&str, &str)], children: Vec<Node>) -> Node {
+    Node::MdxJsxTextElement(MdxJsxTextElement {
+        children,
+        position: None,
+        name: Some(name.to_string()),
+        attributes: attributes(attrs),
+    })
+}
+
+/// Block-level HTML, output as is.
+pub fn raw_flow(html: &str) -> Node {
+    Node::MdxFlowExpression(MdxFlowExpression {
+        value: html.to_string(),
+        position: None,
+        stops: Vec::new(),
+    })
+}
+
+/// Inline HTML, output as is.
+pub fn raw_text(html: &str) -> Node {
+    Node::MdxTextExpression(MdxTextExpression {
+        value: html.to_string(),
+        position: None,
+        stops: Vec::new(),
+    })
+}
+
+pub fn text(value: &str) -> Node {
+    Node::Text(Text {
+        value: value.to_string(),
+        position: None,
+    })
+}
+
+/// Calls `f` on `node` and every node below it, parents first.
+pub fn walk_mut(node: &mut Node, f: &mut impl FnMut(&mut Node)) {
+    f(node);
+    if let Some(children) = node.children_mut() {
+        for child in children {
+            walk_mut(child, f);
+        }
+    }
+}
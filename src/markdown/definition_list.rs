@@ -0,0 +1,134 @@
+use markdown::mdast::Node;
+
+use super::ast;
+
+// Definition Lists
+//
+// A paragraph whose first lines are terms and whose following lines start
+// with `: ` is a definition list, as in PHP Markdown Extra:
+//
+// Term
+// : Definition
+//
+// Lines after a definition that don't start with `: ` continue it.
+// Consecutive lists are merged into one `<dl>`.
+
+const MARKER: &str = ": ";
+
+// Splits inline content into lines at the line breaks in its text.
+fn lines(children: Vec<Node>) -> Vec<Vec<Node>> {
+    let mut lines = vec![Vec::new()];
+    for child in children {
+        let Node::Text(text) = child else {
+            lines.last_mut().unwrap().push(child);
+            continue;
+        };
+        for (index, part) in text.value.split('\n').enumerate() {
+            if index > 0 {
+                lines.push(Vec::new());
+            }
+            if !part.is_empty() {
+                lines.last_mut().unwrap().push(ast::text(part));
+            }
+        }
+    }
+    lines
+}
+
+// Removes the `: ` a definition starts with, if `line` is one.
+fn strip_marker(line: &mut [Node]) -> bool {
+    let Some(Node::Text(text)) = line.first_mut() else {
+        return false;
+    };
+    match text.value.strip_prefix(MARKER) {
+        Some(rest) => {
+            text.value = rest.to_string();
+            true
+        }
+        None => false,
+    }
+}
+
+// The `<dt>` and `<dd>` elements of `paragraph`, if it is a definition list.
+fn items(paragraph: &Node) -> Option<Vec<Node>> {
+    let Node::Paragraph(paragraph) = paragraph else {
+        return None;
+    };
+    let is_candidate = paragraph
+        .children
+        .iter()
+        .any(|child| matches!(child, Node::Text(text) if text.value.contains("\n:")));
+    if !is_candidate {
+        return None;
+    }
+
+    let mut lines = lines(paragraph.children.clone());
+    if strip_marker(&mut lines[0]) {
+        return None;
+    }
+    let mut items = Vec::new();
+    let mut definition: Option<Vec<Node>> = None;
+    for mut line in lines {
+        if strip_marker(&mut line) {
+            if let Some(children) = definition.take() {
+                items.push(ast::flow_element("dd", &[], children));
+            }
+            definition = Some(line);
+        } else if let Some(children) = definition.as_mut() {
+            children.push(ast::text("\n"));
+            children.extend(line);
+        } else {
+            items.push(ast::flow_element("dt", &[], line));
+        }
+    }
+    let children = definition?;
+    items.push(ast::flow_element("dd", &[], children));
+    Some(items)
+}
+
+/// Replaces the definition list paragraphs below `root` with `<dl>` elements.
+pub fn transform(root: &mut Node) {
+    ast::walk_mut(root, &mut |node| {
+        let Some(children) = node.children_mut() else {
+            return;
+        };
+        let mut transformed: Vec<Node> = Vec::new();
+        let mut merge = false; // 直前の子が定義リストかどうか
+        for child in std::mem::take(children) {
+            match items(&child) {
+                Some(items) => {
+                    match transformed.last_mut() {
+                        Some(Node::MdxJsxFlowElement(list)) if merge => list.children.extend(items),
+                        _ => transformed.push(ast::flow_element("dl", &[], items)),
+                    }
+                    merge = true;
+                }
+                None => {
+                    transformed.push(child);
+                    merge = false;
+                }
+            }
+        }
+        *children = transformed;
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markdown::render_transformed;
+
+    fn render(source: &str) -> String {
+        render_transformed(&transform, source).unwrap()
+    }
+
+    #[test]
+    fn test_transform() {
+        assert_eq!(
+            render("Rust\n: *安全*な言語\n: 続き\n\nGo\nGolang\n: 言語\n"),
+            "<dl>\n<dt>Rust</dt>\n<dd><em>安全</em>な言語</dd>\n<dd>続き</dd>\n<dt>Go</dt>\n<dt>Golang</dt>\n<dd>言語</dd>\n</dl>\n"
+        );
+        assert_eq!(render("a\n:b\n"), "<p>a\n:b</p>\n");
+        assert_eq!(render(": a\n: b\n"), "<p>: a\n: b</p>\n");
+    }
+}
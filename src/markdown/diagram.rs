@@ -0,0 +1,274 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use markdown::mdast::Node;
+
+use super::html::escape;
+use super::{ast, Transform};
+use crate::cache;
+use crate::config::Diagram;
+use crate::error::{Error, Location, Result};
+
+// Diagrams
+//
+// Code blocks in a language listed under `[markdown.diagrams]` are diagrams.
+// When the language has a command, the diagram is rendered to inline SVG
+// with it at build time:
+//
+// [markdown.diagrams.dot]
+// command = ["dot", "-Tsvg"]
+//
+// Rendered SVGs are kept in the project's cache directory, outside of the
+// deployed output, by the hash of the command and the diagram, so each
+// diagram is only rendered again once it changes. Those no article uses
+// anymore are removed after the build.
+// Languages without a command are output as `<pre class="diagram-x">` for a
+// script to render in the browser.
+
+pub const CACHE_DIR: &str = "diagrams";
+
+// Runs `command` with `source` on stdin and returns its stdout.
+fn run(command: &[String], source: &str) -> std::result::Result<String, String> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| "empty command".to_string())?;
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to run {}: {}", program, e))?;
+
+    // stdin is written while stdout is read, or a command that outputs before
+    // reading all of its input fills the pipe and both wait forever. It is
+    // dropped after writing so the command sees its end.
+    let stdin = child.stdin.take();
+    let (written, output) = std::thread::scope(|scope| {
+        let writer = scope.spawn(move || match stdin {
+            Some(mut stdin) => stdin.write_all(source.as_bytes()),
+            None => Ok(()),
+        });
+        let output = child.wait_with_output();
+        (writer.join(), output)
+    });
+    let output = output.map_err(|e| format!("failed to run {}: {}", program, e))?;
+    if !output.status.success() {
+        return Err(format!(
+            "{} exited with {}: {}",
+            program,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    match written {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => return Err(format!("failed to write to {}: {}", program, e)),
+        Err(_) => return Err(format!("failed to write to {}", program)),
+    }
+    String::from_utf8(output.stdout).map_err(|_| format!("{} did not output UTF-8", program))
+}
+
+// Drops the XML declaration and doctype, which don't belong in HTML.
+fn inline_svg(svg: &str) -> &str {
+    match svg.find("<svg") {
+        Some(start) => svg[start..].trim_end(),
+        None => svg.trim(),
+    }
+}
+
+pub struct Diagrams {
+    diagrams: BTreeMap<String, Diagram>,
+    cache_dir: PathBuf,
+}
+
+impl Diagrams {
+    pub fn new(diagrams: BTreeMap<String, Diagram>, cache_dir: PathBuf) -> Self {
+        Diagrams {
+            diagrams,
+            cache_dir,
+        }
+    }
+
+    // Name of the cached SVG of `source` rendered with `command`, without extension.
+    fn cache_key(command: &[String], source: &str) -> String {
+        let mut inputs: Vec<&[u8]> = command.iter().map(|arg| arg.as_bytes()).collect();
+        inputs.push(source.as_bytes());
+        cache::hash(&inputs)
+    }
+
+    /// Names of the cached SVGs of the diagrams below `root`, without extension.
+    pub fn cache_keys(&self, root: &Node) -> Vec<String> {
+        let mut keys = Vec::new();
+        let mut nodes = vec![root];
+        while let Some(node) = nodes.pop() {
+            if let Node::Code(code) = node {
+                let diagram = code
+                    .lang
+                    .as_deref()
+                    .and_then(|lang| self.diagrams.get(lang));
+                if let Some(diagram) = diagram.filter(|diagram| !diagram.command.is_empty()) {
+                    keys.push(Diagrams::cache_key(&diagram.command, &code.value));
+                }
+            }
+            nodes.extend(node.children().into_iter().flatten());
+        }
+        keys
+    }
+
+    /// Removes the cached SVGs whose names aren't in `used`.
+    pub fn prune(&self, used: &HashSet<String>) {
+        cache::prune(&self.cache_dir, used);
+    }
+
+    fn svg(&self, command: &[String], source: &str) -> std::result::Result<String, String> {
+        let key = Diagrams::cache_key(command, source);
+        let cached = self.cache_dir.join(format!("{}.svg", key));
+        if let Ok(svg) = fs::read_to_string(&cached) {
+            return Ok(svg);
+        }
+
+        let svg = inline_svg(&run(command, source)?).to_string();
+        cache::write(&cached, &svg);
+        Ok(svg)
+    }
+
+    fn render(
+        &self,
+        lang: &str,
+        diagram: &Diagram,
+        source: &str,
+    ) -> std::result::Result<String, String> {
+        let class = format!("diagram diagram-{}", escape(lang));
+        if diagram.command.is_empty() {
+            return Ok(format!("<pre class=\"{}\">{}</pre>", class, escape(source)));
+        }
+        let svg = self.svg(&diagram.command, source)?;
+        Ok(format!("<div class=\"{}\">{}</div>", class, svg))
+    }
+}
+
+impl Transform for Diagrams {
+    /// Replaces the diagram code blocks below `root`, read from `path`.
+    fn transform(&self, path: &Path, root: &mut Node) -> Result<()> {
+        let mut result = Ok(());
+        ast::walk_mut(root, &mut |node| {
+            let Node::Code(code) = node else {
+                return;
+            };
+            let Some((lang, diagram)) = code
+                .lang
+                .as_deref()
+                .and_then(|lang| self.diagrams.get_key_value(lang))
+            else {
+                return;
+            };
+            if result.is_err() {
+                return;
+            }
+            match self.render(lang, diagram, &code.value) {
+                Ok(html) => *node = ast::raw_flow(&html),
+                Err(message) => {
+                    result = Err(Error::Markdown {
+                        path: path.to_path_buf(),
+                        location: code
+                            .position
+                            .as_ref()
+                            .map(|position| Location::line(position.start.line)),
+                        message: format!("{} diagram: {}", lang, message),
+                    })
+                }
+            }
+        });
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markdown::render_transformed;
+
+    fn diagrams(command: &[&str]) -> BTreeMap<String, Diagram> {
+        let diagram = Diagram {
+            command: command.iter().map(|arg| arg.to_string()).collect(),
+        };
+        BTreeMap::from([("dot".to_string(), diagram)])
+    }
+
+    fn render(
+        diagrams: &BTreeMap<String, Diagram>,
+        cache_dir: &Path,
+        source: &str,
+    ) -> Result<String> {
+        let diagrams = Diagrams::new(diagrams.clone(), cache_dir.to_path_buf());
+        render_transformed(&diagrams, source)
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_transform() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_dir = dir.path().join(CACHE_DIR);
+        let source = "```dot\n<svg>a</svg>\n```\n\n```rust\nfn main() {}\n```\n";
+
+        // `cat` stands in for a renderer that outputs what it's given
+        let html = render(&diagrams(&["cat"]), &cache_dir, source).unwrap();
+        assert!(html.starts_with("<div class=\"diagram diagram-dot\"><svg>a</svg></div>\n"));
+        assert!(html.contains("language-rust"));
+        assert_eq!(
+            render(&diagrams(&["cat"]), &cache_dir, source).unwrap(),
+            html
+        );
+        assert_eq!(fs::read_dir(&cache_dir).unwrap().count(), 1);
+
+        // more than a pipe holds, which `cat` outputs before it has read it all
+        let svg = format!("<svg>{}</svg>", "a".repeat(1 << 20));
+        let html = run(&["cat".to_string()], &svg).unwrap();
+        assert_eq!(html.len(), svg.len());
+
+        let error = render(&diagrams(&["false"]), &cache_dir, source).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("test.md:1: dot diagram: false exited with"));
+    }
+
+    #[test]
+    fn test_client_side() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_dir = dir.path().join(CACHE_DIR);
+        let source = "```dot\n<svg>a</svg>\n```\n";
+
+        let html = render(&diagrams(&[]), &cache_dir, source).unwrap();
+        assert!(
+            html.starts_with("<pre class=\"diagram diagram-dot\">&lt;svg&gt;a&lt;/svg&gt;</pre>\n")
+        );
+        assert!(!cache_dir.exists());
+    }
+
+    #[test]
+    fn test_prune() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache_dir = dir.path().join(CACHE_DIR);
+        let diagrams = Diagrams::new(diagrams(&["dot", "-Tsvg"]), cache_dir.clone());
+        let tree = markdown::to_mdast(
+            "```dot\na -> b\n```\n\n```rust\nfn main() {}\n```\n",
+            &markdown::ParseOptions::gfm(),
+        )
+        .unwrap();
+        let keys = diagrams.cache_keys(&tree);
+        assert_eq!(keys.len(), 1);
+
+        fs::create_dir_all(&cache_dir).unwrap();
+        let used = cache_dir.join(format!("{}.svg", keys[0]));
+        let old = cache_dir.join(format!("{}.svg", cache::hash(&[b"a -> c"])));
+        fs::write(&used, "<svg></svg>").unwrap();
+        fs::write(&old, "<svg></svg>").unwrap();
+        diagrams.prune(&keys.into_iter().collect());
+        assert!(used.exists());
+        assert!(!old.exists());
+    }
+}
@@ -0,0 +1,113 @@
+use std::sync::Arc;
+
+use syntect::highlighting::ThemeSet;
+use syntect::html::{self, ClassStyle, ClassedHTMLGenerator};
+use syntect::parsing::SyntaxSet;
+use syntect::util::LinesWithEndings;
+
+use super::html::escape;
+
+// Highlighting
+//
+// Code blocks are tokenised at build time and emitted as spans with
+// `hl-` prefixed classes. The colours live in a stylesheet generated from
+// the configured theme, so pages need neither JavaScript nor a CDN.
+
+pub const CSS_FILE: &str = "highlight.css";
+
+const CLASS_STYLE: ClassStyle = ClassStyle::SpacedPrefixed { prefix: "hl-" };
+
+#[derive(Clone)]
+pub struct Highlighter {
+    syntaxes: Arc<SyntaxSet>,
+    css: String,
+}
+
+impl Highlighter {
+    /// Loads the bundled grammars and the colour theme `theme`.
+    /// Unknown themes are reported with the list of available ones.
+    pub fn new(theme: &str) -> std::result::Result<Self, String> {
+        let themes = ThemeSet::load_defaults();
+        let Some(theme) = themes.themes.get(theme) else {
+            let names: Vec<&str> = themes.themes.keys().map(String::as_str).collect();
+            return Err(format!(
+                "unknown highlight theme {:?}, expected one of: {}",
+                theme,
+                names.join(", ")
+            ));
+        };
+        let css =
+            html::css_for_theme_with_class_style(theme, CLASS_STYLE).map_err(|e| e.to_string())?;
+
+        Ok(Highlighter {
+            syntaxes: Arc::new(SyntaxSet::load_defaults_newlines()),
+            css,
+        })
+    }
+
+    /// Stylesheet for the classes emitted by `code_block`.
+    pub fn css(&self) -> &str {
+        &self.css
+    }
+
+    // Highlighted HTML of `code`, or None if the language is unknown.
+    fn highlight(&self, lang: &str, code: &str) -> Option<String> {
+        let syntax = self.syntaxes.find_syntax_by_token(lang)?;
+        let mut generator =
+            ClassedHTMLGenerator::new_with_class_style(syntax, &self.syntaxes, CLASS_STYLE);
+        for line in LinesWithEndings::from(code) {
+            generator
+                .parse_html_for_line_which_includes_newline(line)
+                .ok()?;
+        }
+        Some(generator.finalize())
+    }
+
+    /// Renders a fenced code block. Blocks without a known language are
+    /// escaped as they are.
+    pub fn code_block(&self, lang: Option<&str>, code: &str, class: Option<&str>) -> String {
+        // the closing fence's line break is not part of the value
+        let code = if code.is_empty() {
+            String::new()
+        } else {
+            format!("{}\n", code)
+        };
+        let body = lang
+            .and_then(|lang| self.highlight(lang, &code))
+            .unwrap_or_else(|| escape(&code));
+        let class = match class {
+            Some(class) => format!("hl-code {}", escape(class)),
+            None => "hl-code".to_string(),
+        };
+        match lang {
+            Some(lang) => format!(
+                "<pre class=\"{}\"><code class=\"language-{}\">{}</code></pre>\n",
+                class,
+                escape(lang),
+                body
+            ),
+            None => format!("<pre class=\"{}\"><code>{}</code></pre>\n", class, body),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_block() {
+        let highlighter = Highlighter::new("InspiredGitHub").unwrap();
+        assert!(highlighter.css().contains(".hl-code"));
+
+        let html = highlighter.code_block(Some("rust"), "let x = 1;", None);
+        assert!(html.starts_with("<pre class=\"hl-code\"><code class=\"language-rust\">"));
+        assert!(html.contains("<span class=\"hl-storage hl-type hl-rust\">let</span>"));
+
+        let html = highlighter.code_block(Some("no-such-language"), "a < b", Some("p-4"));
+        assert!(html.starts_with("<pre class=\"hl-code p-4\"><code"));
+        assert!(html.contains(">a &lt; b\n</code>"));
+
+        assert!(Highlighter::new("no-such-theme").is_err());
+    }
+}
@@ -0,0 +1,470 @@
+use std::collections::HashMap;
+
+use markdown::mdast::{AlignKind, AttributeContent, AttributeValue, Node};
+
+use super::ast;
+use super::highlight::Highlighter;
+use crate::config::Classes;
+
+// HTML
+//
+// Renders an mdast tree the way markdown-rs' own compiler would with its
+// GFM defaults: raw HTML is escaped and dangerous link protocols dropped.
+// Elements get the classes configured for their name, so pages are styled
+// without any JavaScript. Those made by transforms with a class of their own,
+// like heading anchors, keep just that one.
+
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+// Protocols allowed in link and image URLs; relative URLs are always allowed.
+const SAFE_PROTOCOLS: [&str; 6] = ["http", "https", "mailto", "irc", "ircs", "xmpp"];
+
+// Elements without children or an end tag.
+const VOID_ELEMENTS: [&str; 6] = ["br", "hr", "img", "input", "source", "wbr"];
+
+/// Percent-encodes `url` for an attribute, dropping `javascript:` and other
+/// unsafe protocols.
+pub fn sanitize_url(url: &str) -> String {
+    let protocol_end = url.find([':', '/', '?', '#']);
+    if let Some(end) = protocol_end.filter(|end| url[*end..].starts_with(':')) {
+        let protocol = url[..end].to_ascii_lowercase();
+        if !SAFE_PROTOCOLS.contains(&protocol.as_str()) {
+            return String::new();
+        }
+    }
+
+    let mut encoded = String::new();
+    for c in url.chars() {
+        if c.is_ascii_alphanumeric() || "!#$%'()*+,-./:;=?@_~".contains(c) {
+            encoded.push(c);
+        } else if c == '&' {
+            encoded.push_str("&amp;");
+        } else {
+            let mut buf = [0; 4];
+            for byte in c.encode_utf8(&mut buf).bytes() {
+                encoded.push_str(&format!("%{:02X}", byte));
+            }
+        }
+    }
+    encoded
+}
+
+fn footnote_id(identifier: &str) -> String {
+    sanitize_url(&identifier.to_lowercase())
+}
+
+/// Inline content, which is not put on lines of its own.
+pub fn is_phrasing(node: &Node) -> bool {
+    matches!(
+        node,
+        Node::Text(_)
+            | Node::Emphasis(_)
+            | Node::Strong(_)
+            | Node::Delete(_)
+            | Node::InlineCode(_)
+            | Node::InlineMath(_)
+            | Node::Break(_)
+            | Node::Link(_)
+            | Node::LinkReference(_)
+            | Node::Image(_)
+            | Node::ImageReference(_)
+            | Node::FootnoteReference(_)
+            | Node::MdxJsxTextElement(_)
+            | Node::MdxTextExpression(_)
+    )
+}
+
+/// Lets the raw HTML below `root` through instead of escaping it.
+pub fn allow_raw(root: &mut Node) {
+    ast::walk_mut(root, &mut |node| {
+        let flow = matches!(
+            node,
+            Node::Root(_)
+                | Node::Blockquote(_)
+                | Node::ListItem(_)
+                | Node::FootnoteDefinition(_)
+                | Node::MdxJsxFlowElement(_)
+        );
+        for child in node.children_mut().into_iter().flatten() {
+            if let Node::Html(html) = child {
+                *child = if flow {
+                    ast::raw_flow(&html.value)
+                } else {
+                    ast::raw_text(&html.value)
+                };
+            }
+        }
+    });
+}
+
+pub struct Writer<'a> {
+    highlighter: &'a Highlighter,
+    classes: &'a Classes,
+    definitions: HashMap<String, (String, Option<String>)>,
+    footnotes: HashMap<String, &'a [Node]>,
+    footnote_order: Vec<String>, // 最初に参照された順
+    out: String,
+}
+
+impl<'a> Writer<'a> {
+    pub fn new(highlighter: &'a Highlighter, classes: &'a Classes, root: &'a Node) -> Self {
+        let mut writer = Writer {
+            highlighter,
+            classes,
+            definitions: HashMap::new(),
+            footnotes: HashMap::new(),
+            footnote_order: Vec::new(),
+            out: String::new(),
+        };
+        writer.collect(root);
+        writer
+    }
+
+    // Definitions may come after the references that use them.
+    fn collect(&mut self, node: &'a Node) {
+        match node {
+            Node::Definition(definition) => {
+                // the first definition of an identifier wins
+                self.definitions
+                    .entry(definition.identifier.clone())
+                    .or_insert_with(|| (definition.url.clone(), definition.title.clone()));
+            }
+            Node::FootnoteDefinition(definition) => {
+                self.footnotes
+                    .entry(definition.identifier.clone())
+                    .or_insert(&definition.children);
+            }
+            _ => {}
+        }
+        for child in node.children().into_iter().flatten() {
+            self.collect(child);
+        }
+    }
+
+    pub fn finish(mut self, root: &Node) -> String {
+        self.node(root, false);
+        self.footnote_section();
+        self.out
+    }
+
+    fn children(&mut self, node: &Node, tight: bool) {
+        for child in node.children().into_iter().flatten() {
+            self.node(child, tight);
+        }
+    }
+
+    // ` class="..."` for an element, if it has any classes.
+    fn class(&self, name: &str) -> String {
+        match self.classes.get(name) {
+            Some(class) => format!(" class=\"{}\"", escape(class)),
+            None => String::new(),
+        }
+    }
+
+    fn tag(&mut self, name: &str, node: &Node) {
+        self.out
+            .push_str(&format!("<{}{}>", name, self.class(name)));
+        self.children(node, false);
+        self.out.push_str(&format!("</{}>", name));
+    }
+
+    fn link(&mut self, url: &str, title: Option<&str>, node: &Node) {
+        self.out.push_str(&format!(
+            "<a href=\"{}\"{}",
+            sanitize_url(url),
+            self.class("a")
+        ));
+        if let Some(title) = title {
+            self.out.push_str(&format!(" title=\"{}\"", escape(title)));
+        }
+        self.out.push('>');
+        self.children(node, false);
+        self.out.push_str("</a>");
+    }
+
+    // Elements added by transforms, see `ast`.
+    fn element(&mut self, name: Option<&str>, attributes: &[AttributeContent], node: &Node) {
+        let name = name.unwrap_or("div");
+        let own_class = attributes.iter().any(|attribute| {
+            matches!(attribute, AttributeContent::Property(property) if property.name == "class")
+        });
+        // configured classes are for the elements markdown is made of
+        let class = if own_class {
+            None
+        } else {
+            self.classes.get(name)
+        };
+        self.out.push_str(&format!("<{}", name));
+        for attribute in attributes {
+            if let AttributeContent::Property(property) = attribute {
+                match &property.value {
+                    Some(AttributeValue::Literal(value)) => {
+                        self.out
+                            .push_str(&format!(" {}=\"{}\"", property.name, escape(value)))
+                    }
+                    _ => self.out.push_str(&format!(" {}", property.name)),
+                }
+            }
+        }
+        if let Some(class) = class {
+            self.out.push_str(&format!(" class=\"{}\"", escape(class)));
+        }
+        if VOID_ELEMENTS.contains(&name) {
+            self.out.push_str(" />");
+            return;
+        }
+        self.out.push('>');
+        let phrasing = node
+            .children()
+            .and_then(|children| children.first())
+            .is_none_or(is_phrasing);
+        if matches!(node, Node::MdxJsxFlowElement(_)) && !phrasing {
+            self.out.push('\n');
+        }
+        self.children(node, false);
+        self.out.push_str(&format!("</{}>", name));
+    }
+
+    fn image(&mut self, url: &str, alt: &str, title: Option<&str>) {
+        self.out.push_str(&format!(
+            "<img src=\"{}\" alt=\"{}\"{}",
+            sanitize_url(url),
+            escape(alt),
+            self.class("img")
+        ));
+        if let Some(title) = title {
+            self.out.push_str(&format!(" title=\"{}\"", escape(title)));
+        }
+        self.out.push_str(" />");
+    }
+
+    // `tight` is set for the children of items in a tight list, whose
+    // paragraphs are not wrapped in <p>.
+    fn node(&mut self, node: &Node, tight: bool) {
+        match node {
+            Node::Root(_) => self.children(node, false),
+            Node::Paragraph(_) if tight => {
+                self.children(node, false);
+                self.out.push('\n');
+            }
+            Node::Paragraph(_) => {
+                self.tag("p", node);
+                self.out.push('\n');
+            }
+            Node::Heading(heading) => {
+                self.tag(&format!("h{}", heading.depth), node);
+                self.out.push('\n');
+            }
+            Node::Blockquote(_) => {
+                self.out
+                    .push_str(&format!("<blockquote{}>\n", self.class("blockquote")));
+                self.children(node, false);
+                self.out.push_str("</blockquote>\n");
+            }
+            Node::List(list) => {
+                let loose = list.spread
+                    || list
+                        .children
+                        .iter()
+                        .any(|item| matches!(item, Node::ListItem(item) if item.spread));
+                match (list.ordered, list.start) {
+                    (true, Some(start)) if start != 1 => self.out.push_str(&format!(
+                        "<ol start=\"{}\"{}>\n",
+                        start,
+                        self.class("ol")
+                    )),
+                    (true, _) => self.out.push_str(&format!("<ol{}>\n", self.class("ol"))),
+                    (false, _) => self.out.push_str(&format!("<ul{}>\n", self.class("ul"))),
+                }
+                for item in &list.children {
+                    self.node(item, !loose);
+                }
+                self.out
+                    .push_str(if list.ordered { "</ol>\n" } else { "</ul>\n" });
+            }
+            Node::ListItem(item) => {
+                self.out.push_str(&format!("<li{}>", self.class("li")));
+                match item.checked {
+                    Some(true) => self
+                        .out
+                        .push_str("<input type=\"checkbox\" disabled=\"\" checked=\"\" /> "),
+                    Some(false) => self
+                        .out
+                        .push_str("<input type=\"checkbox\" disabled=\"\" /> "),
+                    None => {}
+                }
+                self.children(node, tight);
+                if self.out.ends_with('\n') {
+                    self.out.pop();
+                }
+                self.out.push_str("</li>\n");
+            }
+            Node::Code(code) => {
+                let html = self.highlighter.code_block(
+                    code.lang.as_deref(),
+                    &code.value,
+                    self.classes.get("pre"),
+                );
+                self.out.push_str(&html);
+            }
+            Node::Math(math) => {
+                self.out.push_str(&super::math::mathml(&math.value, true));
+                self.out.push('\n');
+            }
+            Node::Html(html) => self.out.push_str(&escape(&html.value)),
+            Node::ThematicBreak(_) => self.out.push_str(&format!("<hr{} />\n", self.class("hr"))),
+            Node::Table(table) => {
+                self.out
+                    .push_str(&format!("<table{}>\n", self.class("table")));
+                for (index, row) in table.children.iter().enumerate() {
+                    // header cells label their column
+                    let (cell, scope) = if index == 0 {
+                        ("th", " scope=\"col\"")
+                    } else {
+                        ("td", "")
+                    };
+                    if index == 0 {
+                        self.out
+                            .push_str(&format!("<thead{}>\n", self.class("thead")));
+                    } else if index == 1 {
+                        self.out
+                            .push_str(&format!("<tbody{}>\n", self.class("tbody")));
+                    }
+                    self.out.push_str(&format!("<tr{}>\n", self.class("tr")));
+                    for (column, child) in row.children().into_iter().flatten().enumerate() {
+                        let align = match table.align.get(column) {
+                            Some(AlignKind::Left) => " align=\"left\"",
+                            Some(AlignKind::Right) => " align=\"right\"",
+                            Some(AlignKind::Center) => " align=\"center\"",
+                            _ => "",
+                        };
+                        self.out.push_str(&format!(
+                            "<{}{}{}{}>",
+                            cell,
+                            scope,
+                            align,
+                            self.class(cell)
+                        ));
+                        self.children(child, false);
+                        self.out.push_str(&format!("</{}>\n", cell));
+                    }
+                    self.out.push_str("</tr>\n");
+                    if index == 0 {
+                        self.out.push_str("</thead>\n");
+                    }
+                }
+                if table.children.len() > 1 {
+                    self.out.push_str("</tbody>\n");
+                }
+                self.out.push_str("</table>\n");
+            }
+            Node::MdxJsxFlowElement(element) => {
+                self.element(element.name.as_deref(), &element.attributes, node);
+                self.out.push('\n');
+            }
+            Node::MdxJsxTextElement(element) => {
+                self.element(element.name.as_deref(), &element.attributes, node)
+            }
+            // HTML from transforms, see `ast`
+            Node::MdxFlowExpression(expression) => {
+                self.out.push_str(&expression.value);
+                if !self.out.ends_with('\n') {
+                    self.out.push('\n');
+                }
+            }
+            Node::MdxTextExpression(expression) => self.out.push_str(&expression.value),
+            Node::Text(text) => self.out.push_str(&escape(&text.value)),
+            Node::Emphasis(_) => self.tag("em", node),
+            Node::Strong(_) => self.tag("strong", node),
+            Node::Delete(_) => self.tag("del", node),
+            Node::InlineCode(code) => self.out.push_str(&format!(
+                "<code{}>{}</code>",
+                self.class("code"),
+                escape(&code.value)
+            )),
+            Node::InlineMath(math) => self.out.push_str(&super::math::mathml(&math.value, false)),
+            Node::Break(_) => self.out.push_str("<br />\n"),
+            Node::Link(link) => self.link(&link.url, link.title.as_deref(), node),
+            Node::LinkReference(reference) => {
+                match self.definitions.get(&reference.identifier).cloned() {
+                    Some((url, title)) => self.link(&url, title.as_deref(), node),
+                    None => self.children(node, false),
+                }
+            }
+            Node::Image(image) => self.image(&image.url, &image.alt, image.title.as_deref()),
+            Node::ImageReference(reference) => {
+                if let Some((url, title)) = self.definitions.get(&reference.identifier).cloned() {
+                    self.image(&url, &reference.alt, title.as_deref());
+                }
+            }
+            Node::FootnoteReference(reference) => {
+                let id = footnote_id(&reference.identifier);
+                let first = !self.footnote_order.contains(&reference.identifier);
+                if first {
+                    self.footnote_order.push(reference.identifier.clone());
+                }
+                let number = self
+                    .footnote_order
+                    .iter()
+                    .position(|identifier| identifier == &reference.identifier)
+                    .unwrap_or_default()
+                    + 1;
+                let anchor = if first {
+                    format!(" id=\"user-content-fnref-{}\"", id)
+                } else {
+                    String::new()
+                };
+                self.out.push_str(&format!(
+                    "<sup><a href=\"#user-content-fn-{}\"{} data-footnote-ref=\"\" aria-describedby=\"footnote-label\">{}</a></sup>",
+                    id, anchor, number
+                ));
+            }
+            // front matter, definitions and MDX are not output
+            _ => {}
+        }
+    }
+
+    fn footnote_section(&mut self) {
+        if self.footnote_order.is_empty() {
+            return;
+        }
+        self.out.push_str(
+            "<section data-footnotes=\"\" class=\"footnotes\"><h2 id=\"footnote-label\" class=\"sr-only\">Footnotes</h2>\n",
+        );
+        self.out.push_str(&format!("<ol{}>\n", self.class("ol")));
+        // footnotes may reference other footnotes, which extends the order
+        let mut index = 0;
+        while index < self.footnote_order.len() {
+            let identifier = self.footnote_order[index].clone();
+            let id = footnote_id(&identifier);
+            self.out.push_str(&format!(
+                "<li id=\"user-content-fn-{}\"{}>\n",
+                id,
+                self.class("li")
+            ));
+            if let Some(children) = self.footnotes.get(&identifier).copied() {
+                for child in children {
+                    self.node(child, false);
+                }
+            }
+            let backref = format!(
+                " <a href=\"#user-content-fnref-{}\" data-footnote-backref=\"\" aria-label=\"Back to content\">↩</a>",
+                id
+            );
+            if self.out.ends_with("</p>\n") {
+                self.out.truncate(self.out.len() - "</p>\n".len());
+                self.out.push_str(&format!("{}</p>\n", backref));
+            } else {
+                self.out.push_str(&format!("{}\n", backref));
+            }
+            self.out.push_str("</li>\n");
+            index += 1;
+        }
+        self.out.push_str("</ol>\n</section>\n");
+    }
+}
@@ -0,0 +1,166 @@
+use markdown::mdast::Node;
+
+use super::ast;
+
+// Relative Links
+//
+// An article in a page bundle (`articles/my-post/index.md`) links to the
+// files next to it with relative URLs like `![](diagram.png)`. Its page is
+// served at `/articles/my-post`, against which those URLs would point one
+// directory too high, so they are made absolute here.
+//
+// HTML read outside of the site, like the content of feeds, gets the origin
+// of the site in front of its root-relative URLs as well.
+
+// Whether `url` is relative to the page, rather than to the site or a scheme.
+fn is_relative(url: &str) -> bool {
+    !url.is_empty()
+        && !url.starts_with(['/', '#', '?'])
+        && url::Url::parse(url) == Err(url::ParseError::RelativeUrlWithoutBase)
+}
+
+/// Resolves `url` against the directory `base_url`, e.g. `/articles/my-post`.
+pub fn resolve_url(url: &str, base_url: &str) -> String {
+    if !is_relative(url) {
+        return url.to_string();
+    }
+    let base = format!("{}/", base_url.trim_end_matches('/'));
+    // a dummy origin lets the url crate normalize `./` and `../`
+    match url::Url::parse("http://localhost")
+        .and_then(|origin| origin.join(&base))
+        .and_then(|base| base.join(url))
+    {
+        Ok(resolved) => resolved[url::Position::BeforePath..].to_string(),
+        Err(_) => format!("{}{}", base, url),
+    }
+}
+
+/// Resolves the relative links and images below `root` against `base_url`.
+pub fn resolve(root: &mut Node, base_url: &str) {
+    ast::walk_mut(root, &mut |node| match node {
+        Node::Link(link) => link.url = resolve_url(&link.url, base_url),
+        Node::Image(image) => image.url = resolve_url(&image.url, base_url),
+        Node::Definition(definition) => definition.url = resolve_url(&definition.url, base_url),
+        _ => {}
+    });
+}
+
+// `url` with `origin` in front if it is root-relative.
+fn with_origin(url: &str, origin: &str) -> String {
+    if url.starts_with('/') && !url.starts_with("//") {
+        format!("{}{}", origin, url)
+    } else {
+        url.to_string()
+    }
+}
+
+/// Puts `origin`, e.g. `https://example.com`, in front of the root-relative
+/// URLs in the `href`, `src` and `srcset` attributes of `html`.
+pub fn absolutize_html(html: &str, origin: &str) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut position = 0;
+    let mut in_tag = false; // 属性はタグの中にしかない
+    while let Some(index) = html[position..].find(['<', '>', '=']) {
+        let at = position + index;
+        match html.as_bytes()[at] {
+            b'<' => in_tag = true,
+            b'>' => in_tag = false,
+            _ if in_tag && html[at + 1..].starts_with('"') => {
+                let name = html[..at].rsplit(char::is_whitespace).next().unwrap_or("");
+                let start = at + 2;
+                let Some(length) = html[start..].find('"') else {
+                    break;
+                };
+                let value = &html[start..start + length];
+                output.push_str(&html[position..start]);
+                match name {
+                    "href" | "src" => output.push_str(&with_origin(value, origin)),
+                    "srcset" => {
+                        let candidates: Vec<String> = value
+                            .split(',')
+                            .map(|candidate| match candidate.trim().split_once(' ') {
+                                Some((url, descriptor)) => {
+                                    format!("{} {}", with_origin(url, origin), descriptor)
+                                }
+                                None => with_origin(candidate.trim(), origin),
+                            })
+                            .collect();
+                        output.push_str(&candidates.join(", "));
+                    }
+                    _ => output.push_str(value),
+                }
+                // the closing quote is copied with what follows
+                position = start + length;
+                continue;
+            }
+            _ => {}
+        }
+        output.push_str(&html[position..=at]);
+        position = at + 1;
+    }
+    output.push_str(&html[position..]);
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_url() {
+        let base = "/articles/my-post";
+        assert_eq!(
+            resolve_url("diagram.png", base),
+            "/articles/my-post/diagram.png"
+        );
+        assert_eq!(resolve_url("./a/b.png", base), "/articles/my-post/a/b.png");
+        assert_eq!(resolve_url("../other/b.png", base), "/articles/other/b.png");
+        assert_eq!(
+            resolve_url("a.png?x=1#y", base),
+            "/articles/my-post/a.png?x=1#y"
+        );
+        for url in [
+            "/statics/a.png",
+            "#section",
+            "https://example.com/a.png",
+            "mailto:a@b.c",
+        ] {
+            assert_eq!(resolve_url(url, base), url);
+        }
+
+        let mut tree = markdown::to_mdast(
+            "![a](a.png) [b][1]\n\n[1]: b.pdf\n",
+            &markdown::ParseOptions::gfm(),
+        )
+        .unwrap();
+        resolve(&mut tree, base);
+        let Node::Paragraph(paragraph) = &tree.children().unwrap()[0] else {
+            panic!("expected a paragraph");
+        };
+        assert!(
+            matches!(&paragraph.children[0], Node::Image(image) if image.url == "/articles/my-post/a.png")
+        );
+        assert!(
+            matches!(&tree.children().unwrap()[1], Node::Definition(definition) if definition.url == "/articles/my-post/b.pdf")
+        );
+    }
+
+    #[test]
+    fn test_absolutize_html() {
+        let origin = "https://example.com";
+        assert_eq!(
+            absolutize_html(
+                "<p><a href=\"/articles/a\">a=\"/b\"</a> <a href=\"#c\" title=\"/d\">c</a></p>",
+                origin
+            ),
+            "<p><a href=\"https://example.com/articles/a\">a=\"/b\"</a> <a href=\"#c\" title=\"/d\">c</a></p>"
+        );
+        assert_eq!(
+            absolutize_html(
+                "<source srcset=\"/images/a-16.avif 16w, /images/a-40.avif 40w\" /><img src=\"//cdn.example.com/a.png\" />",
+                origin
+            ),
+            "<source srcset=\"https://example.com/images/a-16.avif 16w, https://example.com/images/a-40.avif 40w\" /><img src=\"//cdn.example.com/a.png\" />"
+        );
+    }
+}
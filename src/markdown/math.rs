@@ -0,0 +1,50 @@
+use pulldown_latex::config::DisplayMode;
+use pulldown_latex::{Parser, RenderConfig, Storage};
+
+use super::html::escape;
+
+// Math
+//
+// `$...$` and `$$...$$` are rendered to MathML at build time, which browsers
+// display without any JavaScript or fonts. LaTeX that can't be rendered is
+// shown in red in place of the formula, the way KaTeX does.
+
+/// Renders `latex` to a `<math>` element, displayed as a block if `display`.
+pub fn mathml(latex: &str, display: bool) -> String {
+    let storage = Storage::new();
+    let parser = Parser::new(latex, &storage);
+    // the annotation is written as is
+    let annotation = escape(latex);
+    let config = RenderConfig {
+        display_mode: if display {
+            DisplayMode::Block
+        } else {
+            DisplayMode::Inline
+        },
+        // 元の LaTeX も残しておく
+        annotation: Some(&annotation),
+        ..RenderConfig::default()
+    };
+
+    let mut mathml = String::new();
+    // only fails when writing fails, which a String doesn't
+    pulldown_latex::push_mathml(&mut mathml, parser, config)
+        .expect("writing to a String can't fail");
+    mathml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mathml() {
+        let html = mathml("x^2", false);
+        assert!(html.starts_with("<math"));
+        assert!(html.contains("<msup>"));
+        assert!(html.contains("<annotation encoding=\"application/x-tex\">x^2</annotation>"));
+
+        assert!(mathml("\\sum_i i", true).contains("display=\"block\""));
+        assert!(mathml("a < b", false).contains(">a &lt; b</annotation>"));
+    }
+}
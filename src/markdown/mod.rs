@@ -0,0 +1,615 @@
+use std::collections::HashSet;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use markdown::mdast::Node;
+use minijinja::ErrorKind;
+
+use crate::config;
+use crate::error::{Error, Location, Result};
+use crate::project;
+
+pub mod alert;
+pub mod ast;
+pub mod definition_list;
+pub mod diagram;
+pub mod highlight;
+pub mod html;
+pub mod link;
+pub mod math;
+pub mod picture;
+pub mod shortcode;
+pub mod toc;
+pub mod typography;
+
+// Markdown
+//
+// Articles are parsed into an mdast tree by markdown-rs and rendered to HTML
+// here instead of by markdown-rs itself, so that code blocks (and anything
+// else that needs more than plain HTML) are rendered our way.
+//
+// Between the two, the tree goes through a list of transforms (alerts,
+// diagrams, the extensions enabled in `[markdown]`, ...) that rewrite it.
+
+pub fn parse_options(markdown: &config::Markdown, math: bool) -> markdown::ParseOptions {
+    markdown::ParseOptions {
+        constructs: markdown::Constructs {
+            frontmatter: true,
+            gfm_footnote_definition: markdown.footnotes,
+            gfm_label_start_footnote: markdown.footnotes,
+            math_flow: math,
+            math_text: math,
+            ..markdown::Constructs::gfm()
+        },
+        ..markdown::ParseOptions::gfm()
+    }
+}
+
+/// A step between parsing an article and writing its HTML.
+pub trait Transform: Send + Sync {
+    fn transform(&self, path: &Path, root: &mut Node) -> Result<()>;
+}
+
+// Transforms that need no settings and can't fail.
+impl<F> Transform for F
+where
+    F: Fn(&mut Node) + Send + Sync,
+{
+    fn transform(&self, _path: &Path, root: &mut Node) -> Result<()> {
+        self(root);
+        Ok(())
+    }
+}
+
+// Moves the location of an error in a shortcode body, which starts at byte
+// `body_offset` of `source`, to where it is in `source`.
+fn in_body(error: Error, source: &str, body_offset: usize) -> Error {
+    match error {
+        Error::Markdown {
+            path,
+            location: Some(location),
+            message,
+        } => {
+            let start = Location::from_offset(source, body_offset, 1);
+            let column = match (location.line, location.column, start.column) {
+                // 本文の 1 行目だけは開始位置の列がずれる
+                (1, Some(column), Some(start)) => Some(start + column - 1),
+                (_, column, _) => column,
+            };
+            Error::Markdown {
+                path,
+                location: Some(Location {
+                    line: start.line + location.line - 1,
+                    column,
+                }),
+                message,
+            }
+        }
+        error => error,
+    }
+}
+
+pub fn error(path: &Path, message: markdown::message::Message) -> Error {
+    let location = message
+        .place
+        .map(|place| match *place {
+            markdown::message::Place::Position(position) => position.start,
+            markdown::message::Place::Point(point) => point,
+        })
+        .map(|point| Location {
+            line: point.line,
+            column: Some(point.column),
+        });
+    Error::Markdown {
+        path: path.to_path_buf(),
+        location,
+        message: message.reason,
+    }
+}
+
+/// Settings of the article being rendered.
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+    pub math: Option<bool>, // None のときは config の [markdown] math
+    /// The URL path relative links are resolved against, for page bundles.
+    pub base_url: Option<String>,
+}
+
+/// A rendered article.
+#[derive(Debug)]
+pub struct Document {
+    pub html: String,
+    pub toc: Vec<toc::Entry>,
+}
+
+// Byte ranges of the code and front matter in `root`, where shortcodes are
+// not expanded.
+fn literal_ranges(root: &Node) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut nodes = vec![root];
+    while let Some(node) = nodes.pop() {
+        match node {
+            Node::Code(_)
+            | Node::InlineCode(_)
+            | Node::Math(_)
+            | Node::InlineMath(_)
+            | Node::Toml(_)
+            | Node::Yaml(_) => {
+                if let Some(position) = node.position() {
+                    ranges.push(position.start.offset..position.end.offset);
+                }
+            }
+            _ => nodes.extend(node.children().into_iter().flatten()),
+        }
+    }
+    ranges
+}
+
+#[derive(Clone)]
+pub struct Renderer {
+    highlighter: highlight::Highlighter,
+    classes: config::Classes,
+    markdown: config::Markdown,
+    env: minijinja::Environment<'static>, // shortcode のテンプレート
+    transforms: Vec<Arc<dyn Transform>>,
+    pictures: Option<Arc<picture::Pictures>>, // transforms にも入っている
+    diagrams: Option<Arc<diagram::Diagrams>>, // transforms にも入っている
+}
+
+impl Renderer {
+    pub fn new(
+        config: &config::Config,
+        env: minijinja::Environment<'static>,
+        paths: &project::Paths,
+    ) -> Result<Self> {
+        let highlighter =
+            highlight::Highlighter::new(&config.highlight.theme).map_err(|message| {
+                Error::Config {
+                    path: paths.config.clone(),
+                    location: None,
+                    message,
+                }
+            })?;
+
+        let markdown = &config.markdown;
+        let mut transforms: Vec<Arc<dyn Transform>> = Vec::new();
+        let diagrams = if markdown.diagrams.is_empty() {
+            None
+        } else {
+            let diagrams = Arc::new(diagram::Diagrams::new(
+                markdown.diagrams.clone(),
+                paths.cache.join(diagram::CACHE_DIR),
+            ));
+            transforms.push(diagrams.clone());
+            Some(diagrams)
+        };
+        transforms.push(Arc::new(alert::transform));
+        if markdown.definition_lists {
+            transforms.push(Arc::new(definition_list::transform));
+        }
+        if markdown.smart_punctuation {
+            transforms.push(Arc::new(typography::transform));
+        }
+        if markdown.raw_html {
+            transforms.push(Arc::new(html::allow_raw));
+        }
+        let pictures = if config.images.enabled {
+            // bundles' images are below the articles, at their page's URL
+            let mut sources = vec![("/articles/".to_string(), paths.content.clone())];
+            for dir in paths.statics_dirs() {
+                sources.push(("/statics/".to_string(), dir));
+            }
+            let pictures = Arc::new(picture::Pictures::new(
+                config.images.clone(),
+                sources,
+                paths.output.join(picture::OUTPUT_DIR),
+            ));
+            transforms.push(pictures.clone());
+            Some(pictures)
+        } else {
+            None
+        };
+
+        Ok(Renderer {
+            highlighter,
+            classes: config.classes.clone(),
+            markdown: markdown.clone(),
+            env,
+            transforms,
+            pictures,
+            diagrams,
+        })
+    }
+
+    /// Adds a transform, run after the built-in ones.
+    pub fn add_transform(&mut self, transform: impl Transform + 'static) {
+        self.transforms.push(Arc::new(transform));
+    }
+
+    pub fn highlighter(&self) -> &highlight::Highlighter {
+        &self.highlighter
+    }
+
+    pub fn classes(&self) -> &config::Classes {
+        &self.classes
+    }
+
+    /// The `<picture>` transform, if `[images]` is enabled.
+    pub fn pictures(&self) -> Option<&picture::Pictures> {
+        self.pictures.as_deref()
+    }
+
+    /// The diagram transform, if `[markdown.diagrams]` has any language.
+    pub fn diagrams(&self) -> Option<&diagram::Diagrams> {
+        self.diagrams.as_deref()
+    }
+
+    fn shortcode(
+        &self,
+        path: &Path,
+        source: &str,
+        settings: &Settings,
+        shortcode: &shortcode::Shortcode,
+        ids: &mut HashSet<String>,
+    ) -> Result<String> {
+        let error = |message: String| Error::Markdown {
+            path: path.to_path_buf(),
+            location: Some(Location::from_offset(source, shortcode.offset, 1)),
+            message,
+        };
+        let name = format!("shortcodes/{}.html", shortcode.name);
+        let template = self.env.get_template(&name).map_err(|e| match e.kind() {
+            ErrorKind::TemplateNotFound => error(format!(
+                "unknown shortcode {:?}, there is no template {}",
+                shortcode.name, name
+            )),
+            _ => Error::Template(e),
+        })?;
+
+        let body = match &shortcode.body {
+            Some(body) => Some(
+                self.render_with_ids(path, body, settings, ids)
+                    .map_err(|e| in_body(e, source, shortcode.body_offset))?
+                    .html,
+            ),
+            None => None,
+        };
+        let mut ctx = minijinja::Value::from_serialize(&shortcode.args);
+        if let Some(body) = body {
+            ctx = minijinja::context! { body => minijinja::Value::from_safe_string(body), ..ctx };
+        }
+        template
+            .render(ctx)
+            .map_err(|e| error(format!("shortcode {:?}: {}", shortcode.name, e)))
+    }
+
+    // The tree of `source` with the URLs of the page, for finding what it
+    // depends on before rendering it. None if it doesn't parse.
+    fn parse(&self, source: &str, settings: &Settings) -> Option<Node> {
+        let math = settings.math.unwrap_or(self.markdown.math);
+        let mut tree = markdown::to_mdast(source, &parse_options(&self.markdown, math)).ok()?;
+        if let Some(base_url) = &settings.base_url {
+            link::resolve(&mut tree, base_url);
+        }
+        Some(tree)
+    }
+
+    /// The image files `source` shows as `<picture>`s, whose contents go into
+    /// its HTML. Empty if it doesn't parse, which `render` reports.
+    pub fn image_files(&self, source: &str, settings: &Settings) -> Vec<PathBuf> {
+        let Some(pictures) = &self.pictures else {
+            return Vec::new();
+        };
+        self.parse(source, settings)
+            .map(|tree| pictures.files(&tree))
+            .unwrap_or_default()
+    }
+
+    /// The names of the cached SVGs of the diagrams in `source`. Empty if it
+    /// doesn't parse, which `render` reports.
+    pub fn diagram_keys(&self, source: &str, settings: &Settings) -> Vec<String> {
+        let Some(diagrams) = &self.diagrams else {
+            return Vec::new();
+        };
+        self.parse(source, settings)
+            .map(|tree| diagrams.cache_keys(&tree))
+            .unwrap_or_default()
+    }
+
+    /// Renders the markdown `source` read from `path` to HTML.
+    pub fn render(&self, path: &Path, source: &str, settings: &Settings) -> Result<Document> {
+        self.render_with_ids(path, source, settings, &mut HashSet::new())
+    }
+
+    // Renders `source` without the heading ids in `ids`, which shortcode
+    // bodies share with the article they end up in.
+    fn render_with_ids(
+        &self,
+        path: &Path,
+        source: &str,
+        settings: &Settings,
+        ids: &mut HashSet<String>,
+    ) -> Result<Document> {
+        let math = settings.math.unwrap_or(self.markdown.math);
+        let options = parse_options(&self.markdown, math);
+        // shortcode bodies are rendered the same way as the article
+        let settings = Settings {
+            math: Some(math),
+            ..settings.clone()
+        };
+        let tree = markdown::to_mdast(source, &options).map_err(|e| error(path, e))?;
+        let (expanded, shortcodes) =
+            shortcode::extract(source, &literal_ranges(&tree)).map_err(|e| Error::Markdown {
+                path: path.to_path_buf(),
+                location: Some(Location::from_offset(source, e.offset, 1)),
+                message: e.message,
+            })?;
+        let mut rendered = Vec::new();
+        for shortcode in &shortcodes {
+            rendered.push(self.shortcode(path, source, &settings, shortcode, ids)?);
+        }
+
+        let mut tree = if shortcodes.is_empty() {
+            tree
+        } else {
+            let mut tree = markdown::to_mdast(&expanded, &options).map_err(|e| error(path, e))?;
+            shortcode::transform(&mut tree, &shortcodes, &rendered);
+            tree
+        };
+        // transforms see the URLs the page will have
+        if let Some(base_url) = &settings.base_url {
+            link::resolve(&mut tree, base_url);
+        }
+        for transform in &self.transforms {
+            transform.transform(path, &mut tree)?;
+        }
+        // headings are final once every transform has run
+        let toc = toc::transform(&mut tree, ids);
+        let html = html::Writer::new(&self.highlighter, &self.classes, &tree).finish(&tree);
+        Ok(Document { html, toc })
+    }
+}
+
+/// Renders `source` to HTML with `transform` as the only transform, for the
+/// tests of transforms.
+#[cfg(test)]
+pub fn render_transformed(transform: &impl Transform, source: &str) -> Result<String> {
+    let mut tree = markdown::to_mdast(source, &markdown::ParseOptions::gfm()).unwrap();
+    transform.transform(Path::new("test.md"), &mut tree)?;
+    let highlighter = highlight::Highlighter::new("InspiredGitHub").unwrap();
+    let classes = config::Classes::new(Default::default());
+    Ok(html::Writer::new(&highlighter, &classes, &tree).finish(&tree))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn renderer(classes: config::Classes) -> Renderer {
+        renderer_with(classes, config::Markdown::default())
+    }
+
+    fn renderer_with(classes: config::Classes, markdown: config::Markdown) -> Renderer {
+        let mut env = minijinja::Environment::new();
+        env.add_template(
+            "shortcodes/youtube.html",
+            "<iframe src=\"https://www.youtube.com/embed/{{ id }}\"></iframe>",
+        )
+        .unwrap();
+        env.add_template(
+            "shortcodes/details.html",
+            "<details><summary>{{ summary }}</summary>{{ body }}</details>",
+        )
+        .unwrap();
+        let config = config::Config {
+            classes,
+            markdown,
+            ..config::Config::default()
+        };
+        let paths = project::Paths::new(
+            Path::new("."),
+            std::path::PathBuf::from("config.toml"),
+            &config.paths,
+            None,
+        );
+        Renderer::new(&config, env, &paths).unwrap()
+    }
+
+    fn render_with(classes: config::Classes, source: &str) -> String {
+        renderer(classes)
+            .render(Path::new("test.md"), source, &Settings::default())
+            .unwrap()
+            .html
+    }
+
+    fn render(source: &str) -> String {
+        render_with(config::Classes::new(Default::default()), source)
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(
+            render("+++\ntitle = \"a\"\n+++\n\n# Title\n\nsome *text* & <b>html</b>\n"),
+            "<h1 id=\"title\">Title<a class=\"heading-anchor\" href=\"#title\" aria-label=\"Permalink\">#</a></h1>\n<p>some <em>text</em> &amp; &lt;b&gt;html&lt;/b&gt;</p>\n"
+        );
+        assert_eq!(
+            render("- a\n- [x] b\n\n1. c\n"),
+            "<ul>\n<li>a</li>\n<li><input type=\"checkbox\" disabled=\"\" checked=\"\" /> b</li>\n</ul>\n<ol>\n<li>c</li>\n</ol>\n"
+        );
+        assert_eq!(
+            render("[Google][1] [x](javascript:alert(1))\n\n[1]: https://www.google.com\n"),
+            "<p><a href=\"https://www.google.com\">Google</a> <a href=\"\">x</a></p>\n"
+        );
+        assert!(render("|a|b|\n|:-|-:|\n|c|d|\n")
+            .contains("<thead>\n<tr>\n<th scope=\"col\" align=\"left\">a</th>\n<th scope=\"col\" align=\"right\">b</th>"));
+        assert!(render("a[^1]\n\n[^1]: note\n")
+            .contains("<li id=\"user-content-fn-1\">\n<p>note <a href=\"#user-content-fnref-1\""));
+    }
+
+    #[test]
+    fn test_classes() {
+        let classes = config::Classes::new(
+            [("h2", "title"), ("p", "text"), ("pre", "block")]
+                .into_iter()
+                .map(|(name, class)| (name.to_string(), class.to_string()))
+                .collect(),
+        );
+        let html = render_with(classes, "## a\n\nb\n\n```\nc\n```\n");
+        assert!(html.starts_with("<h2 id=\"a\" class=\"title\">a"));
+        assert!(html.contains("<p class=\"text\">b</p>"));
+        assert!(html.contains("<pre class=\"hl-code block\">"));
+
+        // anchors made for headings keep their own class
+        let classes = config::Classes::new(
+            [("a", "link")]
+                .into_iter()
+                .map(|(name, class)| (name.to_string(), class.to_string()))
+                .collect(),
+        );
+        assert_eq!(
+            render_with(classes, "## a\n\n[b](/b)\n"),
+            "<h2 id=\"a\">a<a class=\"heading-anchor\" href=\"#a\" aria-label=\"Permalink\">#</a></h2>\n<p><a href=\"/b\" class=\"link\">b</a></p>\n"
+        );
+    }
+
+    #[test]
+    fn test_shortcodes() {
+        assert_eq!(
+            render("a {{< youtube id=\"x\" >}}\n\n{{< youtube id=\"y\" >}}\n"),
+            "<p>a <iframe src=\"https://www.youtube.com/embed/x\"></iframe></p>\n<iframe src=\"https://www.youtube.com/embed/y\"></iframe>\n"
+        );
+        assert_eq!(
+            render("{{< details summary=\"<s>\" >}}\n*b*\n{{< /details >}}\n"),
+            "<details><summary>&lt;s&gt;</summary><p><em>b</em></p>\n</details>\n"
+        );
+        assert_eq!(
+            render("`{{< youtube >}}`\n"),
+            "<p><code>{{&lt; youtube &gt;}}</code></p>\n"
+        );
+
+        let renderer = renderer(config::Classes::new(Default::default()));
+        let error = renderer
+            .render(
+                Path::new("test.md"),
+                "a\n\n{{< tweet id=\"1\" >}}\n",
+                &Settings::default(),
+            )
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "test.md:3:1: unknown shortcode \"tweet\", there is no template shortcodes/tweet.html"
+        );
+
+        // outside of text, shortcodes are left as they were written
+        assert_eq!(
+            render("[x](https://a.example/{{< youtube id=\"y\" >}} \"{{< youtube />}}\") ![{{< youtube />}}](/a.png)\n"),
+            "<p><a href=\"https://a.example/%7B%7B%3C%20youtube%20id=%22y%22%20%3E%7D%7D\" title=\"{{&lt; youtube /&gt;}}\">x</a> <img src=\"/a.png\" alt=\"{{&lt; youtube /&gt;}}\" /></p>\n"
+        );
+
+        // shortcodes in headings are left out of their ids and titles
+        let document = renderer
+            .render(
+                Path::new("test.md"),
+                "## {{< youtube id=\"x\" >}} `a` b\n",
+                &Settings::default(),
+            )
+            .unwrap();
+        assert!(document.html.starts_with(
+            "<h2 id=\"a-b\"><iframe src=\"https://www.youtube.com/embed/x\"></iframe> <code>a</code> b"
+        ));
+        assert_eq!(document.toc[0].title, "a b");
+
+        // headings in bodies are in the same page as the article's
+        let html = render("## a\n\n{{< details >}}\n## a\n{{< /details >}}\n");
+        assert!(html.contains("id=\"a\"") && html.contains("id=\"a-1\""));
+
+        // errors in a body are located in the article
+        for (source, location) in [
+            (
+                "a\n\n{{< details >}}\nb {{< tweet >}}\n{{< /details >}}\n",
+                "4:3",
+            ),
+            ("{{< details >}} {{< tweet >}}{{< /details >}}\n", "1:17"),
+        ] {
+            let error = renderer
+                .render(Path::new("test.md"), source, &Settings::default())
+                .unwrap_err();
+            assert!(error
+                .to_string()
+                .starts_with(&format!("test.md:{}: unknown shortcode", location)));
+        }
+    }
+
+    #[test]
+    fn test_math() {
+        let renderer = renderer(config::Classes::new(Default::default()));
+        let render = |source, math| {
+            renderer
+                .render(
+                    Path::new("test.md"),
+                    source,
+                    &Settings {
+                        math,
+                        ..Default::default()
+                    },
+                )
+                .unwrap()
+                .html
+        };
+
+        // off unless enabled in the config or the front matter
+        assert_eq!(render("$x$\n", None), "<p>$x$</p>\n");
+        assert!(render("a $x^2$ b\n", Some(true)).starts_with("<p>a <math"));
+        assert!(render("$$\n\\frac{1}{2}\n$$\n", Some(true)).contains("display=\"block\""));
+        // nor in shortcodes' bodies
+        assert!(render("{{< details >}}\n$x$\n{{< /details >}}\n", Some(true)).contains("<math"));
+    }
+
+    #[test]
+    fn test_markdown_config() {
+        let render = |markdown, source| {
+            renderer_with(config::Classes::new(Default::default()), markdown)
+                .render(Path::new("test.md"), source, &Settings::default())
+                .unwrap()
+                .html
+        };
+        let source = "\"a\"[^1] <b>c</b>\n\n[^1]: d\n";
+        assert_eq!(
+            render(config::Markdown::default(), source)
+                .lines()
+                .next()
+                .unwrap(),
+            "<p>&quot;a&quot;<sup><a href=\"#user-content-fn-1\" id=\"user-content-fnref-1\" data-footnote-ref=\"\" aria-describedby=\"footnote-label\">1</a></sup> &lt;b&gt;c&lt;/b&gt;</p>"
+        );
+
+        let markdown = config::Markdown {
+            footnotes: false,
+            smart_punctuation: true,
+            raw_html: true,
+            ..config::Markdown::default()
+        };
+        assert_eq!(
+            render(markdown, source),
+            // without footnotes, `[^1]: d` defines a link
+            "<p>“a”<a href=\"d\">^1</a> <b>c</b></p>\n"
+        );
+    }
+
+    #[test]
+    fn test_add_transform() {
+        let mut renderer = renderer(config::Classes::new(Default::default()));
+        renderer.add_transform(|root: &mut Node| {
+            ast::walk_mut(root, &mut |node| {
+                if let Node::Text(text) = node {
+                    text.value = text.value.to_uppercase();
+                }
+            })
+        });
+        let html = renderer
+            .render(Path::new("test.md"), "a *b*\n", &Settings::default())
+            .unwrap()
+            .html;
+        assert_eq!(html, "<p>A <em>B</em></p>\n");
+    }
+}
@@ -0,0 +1,418 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use image::codecs::avif::AvifEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::DynamicImage;
+use markdown::mdast::Node;
+
+use super::{ast, Transform};
+use crate::cache;
+use crate::config::{ImageFormat, Images};
+use crate::error::{Error, Location, Result};
+
+// Pictures
+//
+// Images of articles that are files of the site (in `statics/` or a page
+// bundle) are resized to the widths in `[images]`, encoded to its formats
+// (only AVIF by default), and written as
+//
+// <picture>
+//   <source type="image/avif" srcset="/images/a-....-480.avif 480w, ...">
+//   <img src="/statics/a.png" width=".." height=".." loading="lazy">
+// </picture>
+//
+// so browsers download the smallest variant that fits. The original stays as
+// the fallback, and variants larger than it are left out. Variants are written
+// by the build before the page is rendered, which only names them. They are
+// named by the hash of the image and settings, so they are only encoded again
+// once either changes, and those no page uses anymore are removed after the build.
+
+pub const OUTPUT_DIR: &str = "images";
+
+// AVIF のエンコードは遅いので速度を優先する
+const AVIF_SPEED: u8 = 8;
+
+// Extensions of the images that can be decoded. GIFs may be animated and SVGs
+// scale anyway, so they are left alone.
+const RASTER_EXTENSIONS: [&str; 4] = ["png", "jpg", "jpeg", "webp"];
+
+impl ImageFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ImageFormat::Avif => "avif",
+            ImageFormat::Webp => "webp",
+        }
+    }
+
+    fn mime_type(self) -> &'static str {
+        match self {
+            ImageFormat::Avif => "image/avif",
+            ImageFormat::Webp => "image/webp",
+        }
+    }
+}
+
+pub struct Pictures {
+    images: Images,
+    sources: Vec<(String, PathBuf)>, // URL の接頭辞とそのファイルがあるディレクトリ
+    output_dir: PathBuf,
+}
+
+impl Pictures {
+    /// `sources` maps URL prefixes like `/statics/` to the directories their
+    /// files are read from, tried in order.
+    pub fn new(images: Images, sources: Vec<(String, PathBuf)>, output_dir: PathBuf) -> Self {
+        Pictures {
+            images,
+            sources,
+            output_dir,
+        }
+    }
+
+    // The file `url` points to, if it is an image of the site.
+    fn file(&self, url: &str) -> Option<PathBuf> {
+        let path = url.split(['?', '#']).next()?;
+        let extension = Path::new(path).extension()?.to_str()?.to_lowercase();
+        if !RASTER_EXTENSIONS.contains(&extension.as_str()) || path.contains("..") {
+            return None;
+        }
+        self.sources.iter().find_map(|(prefix, dir)| {
+            let file = dir.join(path.strip_prefix(prefix.as_str())?);
+            file.is_file().then_some(file)
+        })
+    }
+
+    // The widths of the variants of an image `width` pixels wide.
+    fn widths(&self, width: u32) -> Vec<u32> {
+        let mut widths: Vec<u32> = self
+            .images
+            .widths
+            .iter()
+            .copied()
+            .filter(|&w| w > 0 && w < width)
+            .collect();
+        widths.push(width);
+        widths.sort_unstable();
+        widths.dedup();
+        widths
+    }
+
+    // File name of the variant of `file`, with `contents`, `width` pixels wide.
+    fn variant_name(
+        &self,
+        file: &Path,
+        contents: &[u8],
+        width: u32,
+        format: ImageFormat,
+    ) -> String {
+        let hash = cache::hash(&[contents, &[self.images.quality]]);
+        let stem = file
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        format!("{}-{}-{}.{}", stem, &hash[..16], width, format.extension())
+    }
+
+    /// Writes the variants of the image `file` that don't exist yet and
+    /// returns their paths below the output directory, also those left out
+    /// of its `<picture>`. Empty if it can't be read, which rendering reports.
+    pub fn save(&self, file: &Path) -> std::result::Result<Vec<String>, String> {
+        let (Ok(contents), Ok((width, _))) = (fs::read(file), image::image_dimensions(file)) else {
+            return Ok(Vec::new());
+        };
+
+        // decoded once, and only if a variant is missing
+        let mut decoded: Option<DynamicImage> = None;
+        let mut variants = Vec::new();
+        for &format in &self.images.formats {
+            for w in self.widths(width) {
+                let name = self.variant_name(file, &contents, w, format);
+                let path = self.output_dir.join(&name);
+                if !path.exists() {
+                    let image = match &mut decoded {
+                        Some(image) => image,
+                        None => decoded.insert(image::open(file).map_err(|e| e.to_string())?),
+                    };
+                    let resized = image.resize(w, u32::MAX, FilterType::Lanczos3);
+                    self.encode(&resized, format, &path)?;
+                }
+                variants.push(format!("{}/{}", OUTPUT_DIR, name));
+            }
+        }
+        Ok(variants)
+    }
+
+    // The `<source>` elements for the variants of `file` that were saved and
+    // are smaller than it.
+    fn sources(&self, file: &Path, width: u32) -> std::result::Result<Vec<Node>, String> {
+        let contents = fs::read(file).map_err(|e| e.to_string())?;
+        let widths = self.widths(width);
+
+        let mut sources = Vec::new();
+        for format in &self.images.formats {
+            let mut srcset = Vec::new();
+            for &w in &widths {
+                let name = self.variant_name(file, &contents, w, *format);
+                // kept on disk so it isn't encoded again, but never worth downloading
+                let Ok(metadata) = fs::metadata(self.output_dir.join(&name)) else {
+                    continue;
+                };
+                if metadata.len() >= contents.len() as u64 {
+                    continue;
+                }
+                srcset.push(format!("/{}/{} {}w", OUTPUT_DIR, name, w));
+            }
+            if srcset.is_empty() {
+                continue;
+            }
+            sources.push(ast::text_element(
+                "source",
+                &[
+                    ("type", format.mime_type()),
+                    ("srcset", &srcset.join(", ")),
+                    ("sizes", &format!("(max-width: {0}px) 100vw, {0}px", width)),
+                ],
+                Vec::new(),
+            ));
+        }
+        Ok(sources)
+    }
+
+    fn encode(
+        &self,
+        image: &DynamicImage,
+        format: ImageFormat,
+        path: &Path,
+    ) -> std::result::Result<(), String> {
+        fs::create_dir_all(&self.output_dir).map_err(|e| e.to_string())?;
+        // the encoders take 8-bit RGBA, whatever the image was
+        let image = DynamicImage::ImageRgba8(image.to_rgba8());
+        let file = fs::File::create(path).map_err(|e| e.to_string())?;
+        let writer = BufWriter::new(file);
+        let result = match format {
+            ImageFormat::Avif => image.write_with_encoder(AvifEncoder::new_with_speed_quality(
+                writer,
+                AVIF_SPEED,
+                self.images.quality,
+            )),
+            ImageFormat::Webp => image.write_with_encoder(WebPEncoder::new_lossless(writer)),
+        };
+        result.map_err(|e| {
+            // a partly written variant would be taken for a finished one
+            let _ = fs::remove_file(path);
+            e.to_string()
+        })
+    }
+
+    // A `<picture>` for the image at `url`, or just its `<img>` if no variant
+    // is smaller. None if it isn't a file of the site.
+    fn picture(
+        &self,
+        url: &str,
+        alt: &str,
+        title: Option<&str>,
+    ) -> std::result::Result<Option<Node>, String> {
+        let Some(file) = self.file(url) else {
+            return Ok(None);
+        };
+        let (width, height) = image::image_dimensions(&file).map_err(|e| e.to_string())?;
+        let mut children = self.sources(&file, width)?;
+
+        let (width, height) = (width.to_string(), height.to_string());
+        let mut attrs = vec![
+            ("src", url),
+            ("alt", alt),
+            ("width", &width),
+            ("height", &height),
+            ("loading", "lazy"),
+            ("decoding", "async"),
+        ];
+        if let Some(title) = title {
+            attrs.push(("title", title));
+        }
+        let img = ast::text_element("img", &attrs, Vec::new());
+        if children.is_empty() {
+            return Ok(Some(img));
+        }
+        children.push(img);
+        Ok(Some(ast::text_element("picture", &[], children)))
+    }
+
+    /// The files of the images below `root` that become `<picture>`s.
+    pub fn files(&self, root: &Node) -> Vec<PathBuf> {
+        let definitions = definitions(root);
+        let mut files = Vec::new();
+        let mut nodes = vec![root];
+        while let Some(node) = nodes.pop() {
+            let url = match node {
+                Node::Image(image) => Some(&image.url),
+                Node::ImageReference(reference) => {
+                    definitions.get(&reference.identifier).map(|(url, _)| url)
+                }
+                _ => None,
+            };
+            files.extend(url.and_then(|url| self.file(url)));
+            nodes.extend(node.children().into_iter().flatten());
+        }
+        files
+    }
+}
+
+// Where the link references below `root` point, by identifier.
+fn definitions(root: &Node) -> HashMap<String, (String, Option<String>)> {
+    let mut definitions = HashMap::new();
+    let mut nodes = vec![root];
+    while let Some(node) = nodes.pop() {
+        if let Node::Definition(definition) = node {
+            definitions.insert(
+                definition.identifier.clone(),
+                (definition.url.clone(), definition.title.clone()),
+            );
+        }
+        nodes.extend(node.children().into_iter().flatten());
+    }
+    definitions
+}
+
+impl Transform for Pictures {
+    /// Replaces the images below `root` that are files of the site with
+    /// `<picture>` elements.
+    fn transform(&self, path: &Path, root: &mut Node) -> Result<()> {
+        let definitions = definitions(root);
+
+        let mut result = Ok(());
+        ast::walk_mut(root, &mut |node| {
+            let (url, alt, title) = match node {
+                Node::Image(image) => (image.url.clone(), image.alt.clone(), image.title.clone()),
+                Node::ImageReference(reference) => match definitions.get(&reference.identifier) {
+                    Some((url, title)) => (url.clone(), reference.alt.clone(), title.clone()),
+                    None => return,
+                },
+                _ => return,
+            };
+            if result.is_err() {
+                return;
+            }
+            match self.picture(&url, &alt, title.as_deref()) {
+                Ok(Some(picture)) => *node = picture,
+                Ok(None) => {}
+                Err(message) => {
+                    result = Err(Error::Markdown {
+                        path: path.to_path_buf(),
+                        location: node
+                            .position()
+                            .map(|position| Location::line(position.start.line)),
+                        message: format!("image {}: {}", url, message),
+                    })
+                }
+            }
+        });
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::markdown::render_transformed as render;
+
+    // An image that compresses badly, so its variants are smaller than it.
+    fn noise(width: u32, height: u32) -> image::RgbImage {
+        let mut seed: u32 = 1;
+        image::RgbImage::from_fn(width, height, |_, _| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            let [r, g, b, _] = seed.to_le_bytes();
+            image::Rgb([r, g, b])
+        })
+    }
+
+    #[test]
+    fn test_transform() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+        let statics = dir.join("statics");
+        fs::create_dir_all(&statics).unwrap();
+        noise(40, 20).save(statics.join("a.png")).unwrap();
+        // a flat PNG is smaller than any variant
+        image::RgbImage::new(40, 20)
+            .save(statics.join("flat.png"))
+            .unwrap();
+        fs::write(statics.join("broken.png"), "not a png").unwrap();
+
+        let images = Images {
+            widths: vec![16, 960],
+            ..Images::default()
+        };
+        let output_dir = dir.join("generates").join(OUTPUT_DIR);
+        let pictures = Pictures::new(
+            images,
+            vec![("/statics/".to_string(), statics.clone())],
+            output_dir.clone(),
+        );
+
+        // rendering only names variants, so there are none before they are saved
+        assert_eq!(
+            render(&pictures, "![a](/statics/a.png)\n").unwrap(),
+            "<p><img src=\"/statics/a.png\" alt=\"a\" width=\"40\" height=\"20\" loading=\"lazy\" decoding=\"async\" /></p>\n"
+        );
+        assert!(!output_dir.exists());
+
+        let hash = &cache::hash(&[&fs::read(statics.join("a.png")).unwrap(), &[70]])[..16];
+        assert_eq!(
+            pictures.save(&statics.join("a.png")).unwrap(),
+            [16, 40].map(|w| format!("images/a-{}-{}.avif", hash, w))
+        );
+        assert_eq!(pictures.save(&statics.join("flat.png")).unwrap().len(), 2);
+        assert!(output_dir.join(format!("a-{}-16.avif", hash)).exists());
+        let html = render(
+            &pictures,
+            "![a](/statics/a.png \"t\") ![b](/statics/missing.png)\n",
+        )
+        .unwrap();
+        assert_eq!(
+            html,
+            format!(
+                "<p><picture>\
+                 <source type=\"image/avif\" srcset=\"/images/a-{0}-16.avif 16w, /images/a-{0}-40.avif 40w\" sizes=\"(max-width: 40px) 100vw, 40px\" />\
+                 <img src=\"/statics/a.png\" alt=\"a\" width=\"40\" height=\"20\" loading=\"lazy\" decoding=\"async\" title=\"t\" />\
+                 </picture> <img src=\"/statics/missing.png\" alt=\"b\" /></p>\n",
+                hash
+            )
+        );
+
+        let tree = markdown::to_mdast(
+            "![a](/statics/a.png) ![b][b] ![c](https://example.com/c.png)\n\n[b]: /statics/flat.png\n",
+            &markdown::ParseOptions::gfm(),
+        )
+        .unwrap();
+        let mut files = pictures.files(&tree);
+        files.sort();
+        assert_eq!(files, vec![statics.join("a.png"), statics.join("flat.png")]);
+        assert_eq!(fs::read_dir(&output_dir).unwrap().count(), 4);
+
+        assert_eq!(
+            render(&pictures, "![f](/statics/flat.png)\n").unwrap(),
+            "<p><img src=\"/statics/flat.png\" alt=\"f\" width=\"40\" height=\"20\" loading=\"lazy\" decoding=\"async\" /></p>\n"
+        );
+
+        // variants that exist are not encoded again
+        let variant = output_dir.join(format!("a-{}-16.avif", hash));
+        fs::write(&variant, "cached").unwrap();
+        pictures.save(&statics.join("a.png")).unwrap();
+        assert_eq!(fs::read_to_string(&variant).unwrap(), "cached");
+
+        // images that can't be read are left for rendering to report
+        assert!(pictures
+            .save(&statics.join("broken.png"))
+            .unwrap()
+            .is_empty());
+        let error = render(&pictures, "\n![c](/statics/broken.png)\n").unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("test.md:2: image /statics/broken.png:"));
+    }
+}
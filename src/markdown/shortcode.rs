@@ -0,0 +1,363 @@
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use markdown::mdast::Node;
+
+use super::ast;
+
+// Shortcodes
+//
+// Embeds written in articles as
+//
+// {{< youtube id="..." >}}
+// {{< details summary="..." >}}markdown{{< /details >}}
+//
+// and rendered by `templates/shortcodes/<name>.html`, which gets the named
+// arguments as variables and the rendered markdown between the tags as `body`.
+// Closing tags pair with the innermost open shortcode of their name, so one
+// without a body inside a body of the same name is written `{{< name />}}`.
+//
+// Shortcodes are cut out of the source before it is parsed and replaced by
+// placeholders, which are swapped for the rendered templates in the tree.
+// Shortcodes in code are left as they are, and so are those outside of text,
+// e.g. in a link's URL, which get their source back.
+
+const OPEN: &str = "{{<";
+const CLOSE: &str = ">}}";
+
+// Placeholders are private use characters around the shortcode's index.
+const PLACEHOLDER_START: char = '\u{E000}';
+const PLACEHOLDER_END: char = '\u{E001}';
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shortcode {
+    pub name: String,
+    pub args: BTreeMap<String, String>,
+    pub body: Option<String>,
+    pub offset: usize,      // 元の source でのバイト位置
+    pub body_offset: usize, // body の開始位置 (body がなければ offset と同じ)
+    pub source: String,     // 閉じタグまで含めた元の記述
+}
+
+/// A shortcode that could not be parsed, at byte `offset` of the source.
+#[derive(Debug, PartialEq, Eq)]
+pub struct SyntaxError {
+    pub offset: usize,
+    pub message: String,
+}
+
+fn syntax_error(offset: usize, message: impl Into<String>) -> SyntaxError {
+    SyntaxError {
+        offset,
+        message: message.into(),
+    }
+}
+
+// Parses the inside of a tag: `name key="value" key=value`.
+fn parse_tag(tag: &str, offset: usize) -> Result<(String, BTreeMap<String, String>), SyntaxError> {
+    let tag = tag.trim();
+    let (name, mut rest) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+    if name.is_empty() {
+        return Err(syntax_error(offset, "missing shortcode name"));
+    }
+
+    let mut args = BTreeMap::new();
+    loop {
+        rest = rest.trim_start();
+        if rest.is_empty() {
+            break;
+        }
+        let Some((key, value)) = rest.split_once('=') else {
+            return Err(syntax_error(
+                offset,
+                format!(
+                    "expected key=value in shortcode {:?}, found {:?}",
+                    name, rest
+                ),
+            ));
+        };
+        let key = key.trim();
+        if key.is_empty() || key.contains(char::is_whitespace) {
+            return Err(syntax_error(
+                offset,
+                format!("invalid argument name {:?} in shortcode {:?}", key, name),
+            ));
+        }
+        let value = value.trim_start();
+        let (value, remaining) = match value.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').ok_or_else(|| {
+                syntax_error(offset, format!("unclosed quote in shortcode {:?}", name))
+            })?,
+            None => value.split_once(char::is_whitespace).unwrap_or((value, "")),
+        };
+        args.insert(key.to_string(), value.to_string());
+        rest = remaining;
+    }
+    Ok((name.to_string(), args))
+}
+
+// The next tag at or after `from` that is not in `skip`, as (start, end, inside).
+fn next_tag<'a>(
+    source: &'a str,
+    from: usize,
+    skip: &[Range<usize>],
+) -> Result<Option<(usize, usize, &'a str)>, SyntaxError> {
+    let mut from = from;
+    while let Some(index) = source[from..].find(OPEN) {
+        let start = from + index;
+        if let Some(range) = skip.iter().find(|range| range.contains(&start)) {
+            from = range.end;
+            continue;
+        }
+        let inside = start + OPEN.len();
+        let Some(length) = source[inside..].find(CLOSE) else {
+            return Err(syntax_error(start, "unclosed shortcode, expected `>}}`"));
+        };
+        let end = inside + length + CLOSE.len();
+        return Ok(Some((start, end, &source[inside..inside + length])));
+    }
+    Ok(None)
+}
+
+// Splits the `/` of a self-closing tag like `{{< name />}}` off its inside.
+fn self_closing(inside: &str) -> (&str, bool) {
+    match inside.trim_end().strip_suffix('/') {
+        // `url=/a/` ends with a slash too
+        Some(rest)
+            if rest.ends_with(char::is_whitespace)
+                || !rest.trim().contains(char::is_whitespace) =>
+        {
+            (rest, true)
+        }
+        _ => (inside, false),
+    }
+}
+
+// The `{{< /name >}}` at or after `from` that closes an opening `name` tag,
+// skipping the pairs of `name` tags in between and anything in `skip`, as
+// (start, end).
+fn closing_tag(
+    source: &str,
+    from: usize,
+    skip: &[Range<usize>],
+    name: &str,
+) -> Result<Option<(usize, usize)>, SyntaxError> {
+    let mut from = from;
+    let mut depth = 0;
+    while let Some((start, end, inside)) = next_tag(source, from, skip)? {
+        let (inside, closed) = self_closing(inside);
+        match inside.trim().strip_prefix('/') {
+            Some(closing) if closing.trim() == name => {
+                if depth == 0 {
+                    return Ok(Some((start, end)));
+                }
+                depth -= 1;
+            }
+            None if !closed && inside.split_whitespace().next() == Some(name) => depth += 1,
+            _ => {}
+        }
+        from = end;
+    }
+    Ok(None)
+}
+
+/// Cuts the shortcodes out of `source`, skipping those in the byte ranges
+/// `skip`, and returns the source with placeholders in their place.
+pub fn extract(
+    source: &str,
+    skip: &[Range<usize>],
+) -> Result<(String, Vec<Shortcode>), SyntaxError> {
+    let mut output = String::new();
+    let mut shortcodes = Vec::new();
+    let mut position = 0;
+
+    while let Some((start, end, inside)) = next_tag(source, position, skip)? {
+        if inside.trim_start().starts_with('/') {
+            return Err(syntax_error(
+                start,
+                "closing shortcode without an opening one",
+            ));
+        }
+        let (inside, closed) = self_closing(inside);
+        let (name, args) = parse_tag(inside, start)?;
+
+        // a shortcode has a body when it is closed later on
+        let closing = if closed {
+            None
+        } else {
+            closing_tag(source, end, skip, &name)?
+        };
+        let (body, body_offset, end) = match closing {
+            Some((close_start, close_end)) => {
+                (Some(source[end..close_start].to_string()), end, close_end)
+            }
+            None => (None, start, end),
+        };
+
+        output.push_str(&source[position..start]);
+        output.push_str(&format!(
+            "{}{}{}",
+            PLACEHOLDER_START,
+            shortcodes.len(),
+            PLACEHOLDER_END
+        ));
+        shortcodes.push(Shortcode {
+            name,
+            args,
+            body,
+            offset: start,
+            body_offset,
+            source: source[start..end].to_string(),
+        });
+        position = end;
+    }
+    output.push_str(&source[position..]);
+    Ok((output, shortcodes))
+}
+
+// Splits `text` into text and rendered shortcodes.
+fn split(text: &str, rendered: &[String]) -> Vec<Node> {
+    let mut nodes = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(PLACEHOLDER_START) {
+        let after = &rest[start + PLACEHOLDER_START.len_utf8()..];
+        let Some((index, remaining)) = after
+            .split_once(PLACEHOLDER_END)
+            .and_then(|(index, remaining)| Some((index.parse::<usize>().ok()?, remaining)))
+        else {
+            break;
+        };
+        if start > 0 {
+            nodes.push(ast::text(&rest[..start]));
+        }
+        if let Some(html) = rendered.get(index) {
+            nodes.push(ast::raw_text(html));
+        }
+        rest = remaining;
+    }
+    if !rest.is_empty() {
+        nodes.push(ast::text(rest));
+    }
+    nodes
+}
+
+// Puts the source of the shortcodes back in place of the placeholders in `value`.
+fn restore(value: &mut String, shortcodes: &[Shortcode]) {
+    if !value.contains(PLACEHOLDER_START) {
+        return;
+    }
+    for (index, shortcode) in shortcodes.iter().enumerate() {
+        let placeholder = format!("{}{}{}", PLACEHOLDER_START, index, PLACEHOLDER_END);
+        *value = value.replace(&placeholder, &shortcode.source);
+    }
+}
+
+/// Replaces the placeholders below `root` with the rendered `shortcodes`.
+/// A shortcode on a paragraph of its own replaces the paragraph. Those
+/// outside of text can't hold HTML, so they are left as they were written.
+pub fn transform(root: &mut Node, shortcodes: &[Shortcode], rendered: &[String]) {
+    ast::walk_mut(root, &mut |node| {
+        if let Node::Paragraph(paragraph) = node {
+            if let [Node::Text(text)] = paragraph.children.as_slice() {
+                if let [Node::MdxTextExpression(expression)] =
+                    split(&text.value, rendered).as_slice()
+                {
+                    *node = ast::raw_flow(&expression.value);
+                    return;
+                }
+            }
+        }
+        let Some(children) = node.children_mut() else {
+            return;
+        };
+        let mut expanded = Vec::new();
+        for child in std::mem::take(children) {
+            match child {
+                Node::Text(text) if text.value.contains(PLACEHOLDER_START) => {
+                    expanded.extend(split(&text.value, rendered))
+                }
+                child => expanded.push(child),
+            }
+        }
+        *children = expanded;
+    });
+
+    ast::walk_mut(root, &mut |node| match node {
+        Node::Link(link) => {
+            restore(&mut link.url, shortcodes);
+            link.title
+                .iter_mut()
+                .for_each(|title| restore(title, shortcodes));
+        }
+        Node::Image(image) => {
+            restore(&mut image.url, shortcodes);
+            restore(&mut image.alt, shortcodes);
+            image
+                .title
+                .iter_mut()
+                .for_each(|title| restore(title, shortcodes));
+        }
+        Node::Definition(definition) => {
+            restore(&mut definition.url, shortcodes);
+            definition
+                .title
+                .iter_mut()
+                .for_each(|title| restore(title, shortcodes));
+        }
+        Node::ImageReference(reference) => restore(&mut reference.alt, shortcodes),
+        Node::Html(html) => restore(&mut html.value, shortcodes),
+        _ => {}
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract() {
+        let source = "a {{< youtube id=\"x y\" start=10 >}} b\n\n{{< details summary=\"s\" >}}\n**c**\n{{< /details >}}\n`{{< skipped >}}`\n";
+        let code = source.find('`').unwrap()..source.len();
+        let (output, shortcodes) = extract(source, std::slice::from_ref(&code)).unwrap();
+
+        assert_eq!(
+            output,
+            "a \u{E000}0\u{E001} b\n\n\u{E000}1\u{E001}\n`{{< skipped >}}`\n"
+        );
+        assert_eq!(shortcodes[0].name, "youtube");
+        assert_eq!(shortcodes[0].args["id"], "x y");
+        assert_eq!(shortcodes[0].args["start"], "10");
+        assert_eq!(shortcodes[0].body, None);
+        assert_eq!(shortcodes[1].body.as_deref(), Some("\n**c**\n"));
+        assert_eq!(shortcodes[1].body_offset, source.find("\n**c**").unwrap());
+
+        // closing tags may be spaced freely, and those in code don't count
+        let source = "{{< note >}}`{{< /note >}}`{{</note>}} x";
+        let code = 12..27;
+        let (output, shortcodes) = extract(source, std::slice::from_ref(&code)).unwrap();
+        assert_eq!(output, "\u{E000}0\u{E001} x");
+        assert_eq!(shortcodes[0].body.as_deref(), Some("`{{< /note >}}`"));
+
+        // a shortcode without a body doesn't take the closing tag of a later one
+        let (output, shortcodes) =
+            extract("{{< x >}} a {{< x >}}b{{< /x >}} {{< x url=/c/ >}}", &[]).unwrap();
+        assert_eq!(
+            output,
+            "\u{E000}0\u{E001} a \u{E000}1\u{E001} \u{E000}2\u{E001}"
+        );
+        assert_eq!(shortcodes[0].body, None);
+        assert_eq!(shortcodes[1].body.as_deref(), Some("b"));
+        assert_eq!(shortcodes[2].args["url"], "/c/");
+
+        // nor does a self-closing one inside a body of the same name
+        let (_, shortcodes) = extract("{{< x >}}a {{< x />}} b{{< /x >}}", &[]).unwrap();
+        assert_eq!(shortcodes.len(), 1);
+        assert_eq!(shortcodes[0].body.as_deref(), Some("a {{< x />}} b"));
+        let (_, shortcodes) = extract("{{< x a=\"1\" />}}", &[]).unwrap();
+        assert_eq!(shortcodes[0].args["a"], "1");
+
+        assert_eq!(extract("{{< a b >}}", &[]).unwrap_err().offset, 0);
+        assert!(extract("x {{< a", &[]).is_err());
+        assert!(extract("{{< /a >}}", &[]).is_err());
+    }
+}
@@ -0,0 +1,122 @@
+use std::collections::HashSet;
+
+use markdown::mdast::Node;
+use serde::Serialize;
+
+use super::ast;
+use crate::slug;
+
+// Table of Contents
+//
+// Every heading gets an id slugified from its text, so links to a section
+// keep working as long as its title doesn't change. Repeated titles get
+// `-1`, `-2`, ... appended, like on GitHub.
+
+/// A heading in the table of contents, with the headings below it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Entry {
+    pub depth: u8,
+    pub id: String,
+    pub title: String,
+    pub children: Vec<Entry>,
+}
+
+// Nests `entry` under the last entry of a lower depth.
+fn insert(entries: &mut Vec<Entry>, entry: Entry) {
+    match entries.last_mut() {
+        Some(last) if last.depth < entry.depth => insert(&mut last.children, entry),
+        _ => entries.push(entry),
+    }
+}
+
+// The text of `node`, without the HTML that shortcodes and transforms put in.
+fn plain_text(node: &Node) -> String {
+    match node {
+        Node::Text(text) => text.value.clone(),
+        Node::InlineCode(code) => code.value.clone(),
+        Node::MdxTextExpression(_) | Node::MdxFlowExpression(_) | Node::Html(_) => String::new(),
+        node => node
+            .children()
+            .into_iter()
+            .flatten()
+            .map(plain_text)
+            .collect(),
+    }
+}
+
+fn unique_id(title: &str, used: &mut HashSet<String>) -> String {
+    let base = match slug::slugify(title) {
+        slug if slug.is_empty() => "section".to_string(),
+        slug => slug,
+    };
+    let mut id = base.clone();
+    let mut count = 0;
+    while used.contains(&id) {
+        count += 1;
+        id = format!("{}-{}", base, count);
+    }
+    used.insert(id.clone());
+    id
+}
+
+/// Gives every heading below `root` an id not in `used` and an anchor link,
+/// and returns the headings as a nested table of contents.
+pub fn transform(root: &mut Node, used: &mut HashSet<String>) -> Vec<Entry> {
+    let mut entries = Vec::new();
+
+    ast::walk_mut(root, &mut |node| {
+        if !matches!(node, Node::Heading(_)) {
+            return;
+        }
+        let title = plain_text(node).trim().to_string();
+        let Node::Heading(heading) = node else {
+            return;
+        };
+        let id = unique_id(&title, used);
+        let depth = heading.depth;
+        let mut children = std::mem::take(&mut heading.children);
+        children.push(ast::text_element(
+            "a",
+            &[
+                ("class", "heading-anchor"),
+                ("href", &format!("#{}", id)),
+                ("aria-label", "Permalink"),
+            ],
+            vec![ast::text("#")],
+        ));
+        *node = ast::flow_element(&format!("h{}", depth), &[("id", &id)], children);
+
+        insert(
+            &mut entries,
+            Entry {
+                depth,
+                id,
+                title,
+                children: Vec::new(),
+            },
+        );
+    });
+
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transform() {
+        let mut tree = markdown::to_mdast(
+            "## 概要\n\n### Rust *1*\n\n## 概要\n\n# !!\n",
+            &markdown::ParseOptions::gfm(),
+        )
+        .unwrap();
+        let mut used = HashSet::from(["rust-1".to_string()]);
+        let toc = transform(&mut tree, &mut used);
+
+        let ids: Vec<&str> = toc.iter().map(|entry| entry.id.as_str()).collect();
+        assert_eq!(ids, ["概要", "概要-1", "section"]);
+        assert_eq!(toc[0].children[0].id, "rust-1-1");
+        assert_eq!(toc[0].children[0].title, "Rust 1");
+    }
+}
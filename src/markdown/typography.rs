@@ -0,0 +1,76 @@
+use markdown::mdast::Node;
+
+use super::ast;
+use super::html::is_phrasing;
+
+// Smart Punctuation
+//
+// Replaces ASCII punctuation in text with its typographic form, like
+// SmartyPants: `"a"` becomes “a”, `it's` it’s, `--` an en dash, `---` an em
+// dash and `...` an ellipsis. Code is left alone, since it is not text.
+
+// Whether a quote after `previous` opens a quotation.
+fn opens(previous: char) -> bool {
+    previous.is_whitespace() || "([{-–—“‘".contains(previous)
+}
+
+// `previous` is the character before `text`, and is updated to its last one.
+fn smarten(text: &str, previous: &mut char) -> String {
+    let chars: Vec<char> = text.chars().collect();
+    let mut output = String::new();
+    let mut index = 0;
+    while index < chars.len() {
+        let rest = &chars[index..];
+        let (replacement, length) = match rest {
+            ['-', '-', '-', ..] => ('—', 3),
+            ['-', '-', ..] => ('–', 2),
+            ['.', '.', '.', ..] => ('…', 3),
+            ['"', ..] if opens(*previous) => ('“', 1),
+            ['"', ..] => ('”', 1),
+            ['\'', ..] if opens(*previous) => ('‘', 1),
+            ['\'', ..] => ('’', 1),
+            [c, ..] => (*c, 1),
+            [] => unreachable!(),
+        };
+        output.push(replacement);
+        *previous = replacement;
+        index += length;
+    }
+    output
+}
+
+/// Smartens the punctuation of the text below `root`.
+pub fn transform(root: &mut Node) {
+    // blocks start a new line, where quotes open
+    let mut previous = ' ';
+    ast::walk_mut(root, &mut |node| match node {
+        Node::Text(text) => text.value = smarten(&text.value, &mut previous),
+        Node::Break(_) => previous = ' ',
+        node if is_phrasing(node) => {
+            // code and the like end in something other than a space
+            if node.children().is_none() {
+                previous = 'x';
+            }
+        }
+        _ => previous = ' ',
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_smarten() {
+        let mut previous = ' ';
+        assert_eq!(
+            smarten("\"It's\" -- 'a'... 1---2", &mut previous),
+            "“It’s” – ‘a’… 1—2"
+        );
+
+        let mut tree =
+            markdown::to_mdast("\"*a*\" `\"b\"`\n", &markdown::ParseOptions::gfm()).unwrap();
+        transform(&mut tree);
+        assert_eq!(tree.to_string(), "“a” \"b\"");
+    }
+}
@@ -0,0 +1,99 @@
+use std::path::PathBuf;
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Pagination {
+    pub current_page: usize,
+    pub total_pages: usize,
+    pub prev: Option<String>,
+    pub next: Option<String>,
+}
+
+pub struct Page<'a, T> {
+    pub items: &'a [T],
+    pub url: String,
+    pub pagination: Pagination,
+}
+
+impl<T> Page<'_, T> {
+    /// Path of the generated file relative to the output directory, e.g. `articles/page/2.html`.
+    pub fn output_path(&self) -> PathBuf {
+        PathBuf::from(format!("{}.html", self.url.trim_start_matches('/')))
+    }
+}
+
+// The first page lives at `url` itself, the rest at `url/page/<n>`.
+fn page_url(url: &str, number: usize) -> String {
+    if number == 1 {
+        url.to_string()
+    } else {
+        format!("{}/page/{}", url, number)
+    }
+}
+
+/// Splits `items` into pages of `per_page` items served under `url`.
+/// A `per_page` of 0 puts everything on a single page.
+pub fn paginate<'a, T>(items: &'a [T], per_page: usize, url: &str) -> Vec<Page<'a, T>> {
+    let chunks: Vec<&[T]> = if items.is_empty() || per_page == 0 {
+        vec![items]
+    } else {
+        items.chunks(per_page).collect()
+    };
+    let total_pages = chunks.len();
+
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(index, items)| {
+            let number = index + 1;
+            Page {
+                items,
+                url: page_url(url, number),
+                pagination: Pagination {
+                    current_page: number,
+                    total_pages,
+                    prev: (number > 1).then(|| page_url(url, number - 1)),
+                    next: (number < total_pages).then(|| page_url(url, number + 1)),
+                },
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn test_paginate() {
+        let items: Vec<usize> = (0..5).collect();
+        let pages = paginate(&items, 2, "/articles");
+
+        assert_eq!(pages.len(), 3);
+        assert_eq!(pages[0].items, &[0, 1]);
+        assert_eq!(pages[0].url, "/articles");
+        assert_eq!(pages[0].output_path(), Path::new("articles.html"));
+        assert_eq!(
+            pages[1].pagination,
+            Pagination {
+                current_page: 2,
+                total_pages: 3,
+                prev: Some("/articles".to_string()),
+                next: Some("/articles/page/3".to_string()),
+            }
+        );
+        assert_eq!(pages[2].items, &[4]);
+        assert_eq!(pages[2].pagination.next, None);
+    }
+
+    #[test]
+    fn test_paginate_single_page() {
+        let items: Vec<usize> = Vec::new();
+        assert_eq!(paginate(&items, 2, "/articles").len(), 1);
+
+        let items: Vec<usize> = (0..5).collect();
+        assert_eq!(paginate(&items, 0, "/articles")[0].items.len(), 5);
+    }
+}
@@ -1,7 +1,12 @@
 use std::fs;
 use std::path;
 
+use crate::config;
 use crate::config::Config;
+use crate::error::{Error, Result};
+use crate::theme::Theme;
+
+pub const CONFIG_FILE: &str = "config.toml";
 
 pub struct Project {
     root: path::PathBuf,
@@ -13,26 +18,124 @@ pub struct Project {
 // ├── config.toml -- Project configuration
 // ├── articles/   -- article files
 // ├── generates/   -- Generates files
-// └── statics/    -- Static files (images, css, js, etc.)
+// ├── statics/    -- Static files (images, css, js, etc.)
+// ├── templates/  -- Overrides of the built-in templates (optional)
+// ├── themes/     -- Themes selectable with `theme = "<name>"` (optional)
+// └── .cache/     -- Work files kept between builds, not deployed
 //
+// Every directory except config.toml can be moved with the [paths] section.
+
+/// Directories of a project, resolved against its root.
+#[derive(Debug, Clone)]
+pub struct Paths {
+    pub root: path::PathBuf,
+    pub config: path::PathBuf,
+    pub content: path::PathBuf,
+    pub output: path::PathBuf,
+    pub statics: path::PathBuf,
+    pub templates: path::PathBuf,
+    pub cache: path::PathBuf,
+    pub theme: Option<Theme>,
+}
+
+fn resolve(root: &path::Path, path: &path::Path) -> path::PathBuf {
+    // keep paths relative when running from the project root, so errors
+    // read `articles/foo.md` rather than `./articles/foo.md`
+    if path.is_absolute() || root == path::Path::new(".") {
+        path.to_path_buf()
+    } else {
+        root.join(path)
+    }
+}
+
+impl Paths {
+    pub fn new(
+        root: &path::Path,
+        config_path: path::PathBuf,
+        paths: &config::Paths,
+        theme: Option<Theme>,
+    ) -> Self {
+        Paths {
+            root: root.to_path_buf(),
+            config: config_path,
+            content: resolve(root, &paths.content),
+            output: resolve(root, &paths.output),
+            statics: resolve(root, &paths.statics),
+            templates: resolve(root, &paths.templates),
+            cache: resolve(root, &paths.cache),
+            theme,
+        }
+    }
+
+    /// Reads the config at `config_path`, with the defaults of the theme it
+    /// selects, and resolves the project's directories.
+    pub fn load(root: &path::Path, config_path: path::PathBuf) -> Result<(Config, Self)> {
+        let selection = config::theme_selection(&config_path)?;
+        let theme = match &selection.theme {
+            Some(name) => Theme::find(&resolve(root, &selection.paths.themes), name, &config_path)?,
+            None => None,
+        };
+
+        let defaults = theme
+            .as_ref()
+            .map(Theme::config)
+            .filter(|path| path.exists());
+        let config = match defaults {
+            Some(defaults) => config::from_file_with_defaults(config_path.clone(), &defaults)?,
+            None => config::from_file(config_path.clone())?,
+        };
+
+        let paths = Paths::new(root, config_path, &config.paths, theme);
+        Ok((config, paths))
+    }
+
+    /// Template directories, highest precedence first. The built-in
+    /// templates come after all of them.
+    pub fn template_dirs(&self) -> Vec<path::PathBuf> {
+        let mut dirs = vec![self.templates.clone()];
+        dirs.extend(self.theme.as_ref().map(Theme::templates));
+        dirs
+    }
+
+    /// Static file directories, highest precedence first.
+    pub fn statics_dirs(&self) -> Vec<path::PathBuf> {
+        let mut dirs = vec![self.statics.clone()];
+        dirs.extend(self.theme.as_ref().map(Theme::statics));
+        dirs
+    }
+
+    /// Location of config.toml, unless it was given explicitly.
+    pub fn default_config(root: &path::Path) -> path::PathBuf {
+        resolve(root, path::Path::new(CONFIG_FILE))
+    }
+}
 
 impl Project {
     pub fn new(root: path::PathBuf) -> Self {
         Project { root }
     }
 
-    pub fn create(&self) -> std::io::Result<()> {
-        fs::create_dir_all(self.root.join("articles"))?;
-        fs::create_dir_all(self.root.join("generates/articles"))?;
-        fs::create_dir_all(self.root.join("generates/statics"))?;
-        fs::create_dir_all(self.root.join("statics"))?;
-        let _ = Config::default().to_file(self.root.join("config.toml"));
-
-        Ok(())
+    pub fn create(&self) -> Result<()> {
+        let config = Config::default();
+        let paths = Paths::new(
+            &self.root,
+            Paths::default_config(&self.root),
+            &config.paths,
+            None,
+        );
+        for dir in [
+            paths.content.clone(),
+            paths.output.join("articles"),
+            paths.output.join("statics"),
+            paths.statics.clone(),
+        ] {
+            fs::create_dir_all(&dir).map_err(|e| Error::io(&dir, e))?;
+        }
+        config.to_file(paths.config)
     }
 
-    pub fn read_file(&self, path: &str) -> String {
+    pub fn read_file(&self, path: &str) -> Result<String> {
         let full_path = self.root.join(path);
-        fs::read_to_string(full_path).expect("Unable to read file")
+        fs::read_to_string(&full_path).map_err(|e| Error::io(&full_path, e))
     }
 }
@@ -1,34 +1,102 @@
 use std::path::{Path, PathBuf};
 
 use rocket::fs::NamedFile;
-use rocket::{figment, get, routes, Build, Rocket};
+use rocket::response::content::RawHtml;
+use rocket::response::stream::{Event, EventStream};
+use rocket::tokio::select;
+use rocket::tokio::sync::broadcast::{self, error::RecvError};
+use rocket::{figment, get, routes, Build, Responder, Rocket, Shutdown, State};
+
+const RELOAD_PATH: &str = "/__overture/reload";
 
 pub struct Server {
     address: String,
     port: u16,
+    output: PathBuf,
+    reload: Option<broadcast::Sender<()>>,
+}
+
+struct Site {
+    output: PathBuf, // 生成されたファイルのディレクトリ
+    reload: Option<broadcast::Sender<()>>,
+}
+
+#[derive(Responder)]
+enum Page {
+    File(NamedFile),
+    Html(RawHtml<String>),
+}
+
+fn inject_reload_script(html: &str) -> String {
+    let script = format!(
+        "<script>new EventSource(\"{}\").onmessage = () => location.reload();</script>",
+        RELOAD_PATH
+    );
+    match html.rfind("</body>") {
+        Some(pos) => format!("{}{}{}", &html[..pos], script, &html[pos..]),
+        None => format!("{}{}", html, script),
+    }
+}
+
+async fn open(path: PathBuf, site: &Site) -> Option<Page> {
+    let is_html = path.extension().is_some_and(|ext| ext == "html");
+    if site.reload.is_some() && is_html {
+        let html = rocket::tokio::fs::read_to_string(path).await.ok()?;
+        return Some(Page::Html(RawHtml(inject_reload_script(&html))));
+    }
+    NamedFile::open(path).await.ok().map(Page::File)
 }
 
 #[get("/")]
-async fn index() -> Option<NamedFile> {
-    NamedFile::open("generates/index.html").await.ok()
+async fn index(site: &State<Site>) -> Option<Page> {
+    open(site.output.join("index.html"), site).await
 }
 
 #[get("/<file..>")]
-async fn file(file: PathBuf) -> Option<NamedFile> {
-    // 拡張子がないときは .html をつける
-    let file = if file.extension().is_none() {
-        file.with_extension("html")
+async fn file(file: PathBuf, site: &State<Site>) -> Option<Page> {
+    // 拡張子がないときは .html を、それもなくディレクトリのときは index.html をつける
+    let path = site.output.join(&file);
+    let path = if file.extension().is_none() && path.with_extension("html").exists() {
+        path.with_extension("html")
+    } else if path.is_dir() {
+        path.join("index.html")
     } else {
-        file
+        path
     };
-    NamedFile::open(Path::new("generates/").join(file))
-        .await
-        .ok()
+    open(path, site).await
+}
+
+#[get("/__overture/reload")]
+fn reload(site: &State<Site>, mut shutdown: Shutdown) -> Option<EventStream![]> {
+    let mut rx = site.reload.as_ref()?.subscribe();
+    Some(EventStream! {
+        loop {
+            select! {
+                msg = rx.recv() => match msg {
+                    Ok(_) | Err(RecvError::Lagged(_)) => yield Event::data("reload"),
+                    Err(RecvError::Closed) => break,
+                },
+                _ = &mut shutdown => break,
+            }
+        }
+    })
 }
 
 impl Server {
-    pub fn new(address: String, port: u16) -> Server {
-        Server { address, port }
+    /// Serves the files generated into `output`.
+    pub fn new(address: String, port: u16, output: &Path) -> Server {
+        Server {
+            address,
+            port,
+            output: output.to_path_buf(),
+            reload: None,
+        }
+    }
+
+    /// Injects a script into served pages that reloads them whenever `reload` fires.
+    pub fn with_live_reload(mut self, reload: broadcast::Sender<()>) -> Server {
+        self.reload = Some(reload);
+        self
     }
 
     fn rocket(&self) -> Rocket<Build> {
@@ -37,10 +105,30 @@ impl Server {
             .merge(("port", self.port))
             .merge(("address", self.address.clone()));
 
-        rocket::custom(config).mount("/", routes![file, index])
+        rocket::custom(config)
+            .manage(Site {
+                output: self.output.clone(),
+                reload: self.reload.clone(),
+            })
+            .mount("/", routes![file, index, reload])
     }
 
     pub async fn serve(&self) {
         self.rocket().launch().await.ok();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inject_reload_script() {
+        let html = inject_reload_script("<html><body><p>hi</p></body></html>");
+        assert!(html.contains(RELOAD_PATH));
+        assert!(html.ends_with("</script></body></html>"));
+
+        let html = inject_reload_script("<p>hi</p>");
+        assert!(html.starts_with("<p>hi</p><script>"));
+    }
+}
@@ -0,0 +1,122 @@
+use chrono::DateTime;
+use chrono_tz::Tz;
+
+use crate::config::Robots;
+use crate::error::{Error, Result};
+
+pub const SITEMAP_FILE: &str = "sitemap.xml";
+pub const ROBOTS_FILE: &str = "robots.txt";
+
+pub struct Entry {
+    pub path: String, // サイト内のパス (e.g. /articles/test)
+    pub lastmod: Option<DateTime<Tz>>,
+}
+
+impl Entry {
+    pub fn new(path: &str) -> Self {
+        Entry {
+            path: path.to_string(),
+            lastmod: None,
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn join(base_url: &url::Url, path: &str) -> Result<url::Url> {
+    base_url.join(path).map_err(|source| Error::Url {
+        url: base_url.to_string(),
+        source,
+    })
+}
+
+pub fn sitemap(base_url: &url::Url, entries: &[Entry]) -> Result<String> {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+    );
+    for entry in entries {
+        let loc = join(base_url, &entry.path)?;
+        xml.push_str("  <url>\n");
+        xml.push_str(&format!("    <loc>{}</loc>\n", escape(loc.as_str())));
+        if let Some(lastmod) = entry.lastmod {
+            xml.push_str(&format!(
+                "    <lastmod>{}</lastmod>\n",
+                lastmod.to_rfc3339()
+            ));
+        }
+        xml.push_str("  </url>\n");
+    }
+    xml.push_str("</urlset>\n");
+    Ok(xml)
+}
+
+pub fn robots(base_url: &url::Url, config: &Robots) -> Result<String> {
+    let mut txt = format!("User-agent: {}\n", config.user_agent);
+    for path in &config.allow {
+        txt.push_str(&format!("Allow: {}\n", path));
+    }
+    for path in &config.disallow {
+        txt.push_str(&format!("Disallow: {}\n", path));
+    }
+    if config.allow.is_empty() && config.disallow.is_empty() {
+        // an empty Disallow allows everything
+        txt.push_str("Disallow:\n");
+    }
+    txt.push_str(&format!(
+        "\nSitemap: {}\n",
+        join(base_url, &format!("/{}", SITEMAP_FILE))?
+    ));
+    Ok(txt)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::Asia::Tokyo;
+
+    #[test]
+    fn test_sitemap() {
+        let base_url = url::Url::parse("https://www.ucpr.dev").unwrap();
+        let entries = vec![
+            Entry::new("/"),
+            Entry {
+                path: "/articles/a&b".to_string(),
+                lastmod: Some(
+                    DateTime::parse_from_rfc3339("2024-04-25T00:00:00+09:00")
+                        .unwrap()
+                        .with_timezone(&Tokyo),
+                ),
+            },
+        ];
+        let xml = sitemap(&base_url, &entries).unwrap();
+
+        assert!(xml.contains("<loc>https://www.ucpr.dev/</loc>"));
+        assert!(xml.contains("<loc>https://www.ucpr.dev/articles/a&amp;b</loc>"));
+        assert!(xml.contains("<lastmod>2024-04-25T00:00:00+09:00</lastmod>"));
+    }
+
+    #[test]
+    fn test_robots() {
+        let base_url = url::Url::parse("https://www.ucpr.dev").unwrap();
+
+        let txt = robots(&base_url, &Robots::default()).unwrap();
+        assert_eq!(
+            txt,
+            "User-agent: *\nDisallow:\n\nSitemap: https://www.ucpr.dev/sitemap.xml\n"
+        );
+
+        let config = Robots {
+            disallow: vec!["/about".to_string()],
+            ..Robots::default()
+        };
+        let txt = robots(&base_url, &config).unwrap();
+        assert!(txt.starts_with("User-agent: *\nDisallow: /about\n"));
+    }
+}
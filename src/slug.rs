@@ -0,0 +1,41 @@
+/// Turns `text` into a URL-safe slug.
+///
+/// Letters, digits and `_` of any script are kept (so Japanese text stays
+/// readable in URLs), letters are lowercased in every script that has case,
+/// and every other run of characters becomes a single `-`.
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    let mut pending_dash = false;
+
+    for c in text.chars() {
+        if c.is_alphanumeric() || c == '_' {
+            if pending_dash && !slug.is_empty() {
+                slug.push('-');
+            }
+            pending_dash = false;
+            slug.extend(c.to_lowercase());
+        } else {
+            pending_dash = true;
+        }
+    }
+
+    slug
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slugify() {
+        assert_eq!(slugify("Rust"), "rust");
+        assert_eq!(slugify("  GitHub Actions!! "), "github-actions");
+        assert_eq!(slugify("C++ / C#"), "c-c");
+        assert_eq!(
+            slugify("静的サイト ジェネレーター"),
+            "静的サイト-ジェネレーター"
+        );
+        assert_eq!(slugify("Ελληνικά Ü"), "ελληνικά-ü");
+        assert_eq!(slugify("!!!"), "");
+    }
+}
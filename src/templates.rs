@@ -0,0 +1,171 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::cache;
+use crate::error::{Error, Result};
+
+// Templates
+//
+// Pages are rendered with the built-in templates unless the project's
+// templates directory (or its theme's) has a file of the same name, so a
+// project only keeps the templates it actually changes (e.g. just
+// components/header.html).
+
+#[cfg(feature = "bundled")]
+static BUILTIN: &[(&str, &str)] = include!(concat!(env!("OUT_DIR"), "/templates.rs"));
+
+// Without the bundled feature the templates are read from the source tree,
+// so editing them doesn't need a rebuild of overture.
+// The path is absolute, so overture runs from any directory.
+#[cfg(not(feature = "bundled"))]
+const BUILTIN_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/templates");
+
+/// Directory the built-in templates are read from, if they aren't bundled.
+pub fn builtin_dir() -> Option<&'static Path> {
+    #[cfg(feature = "bundled")]
+    {
+        None
+    }
+    #[cfg(not(feature = "bundled"))]
+    {
+        Some(Path::new(BUILTIN_DIR))
+    }
+}
+
+/// Name and source of every built-in template.
+pub fn builtin() -> Result<Vec<(String, String)>> {
+    #[cfg(feature = "bundled")]
+    {
+        Ok(BUILTIN
+            .iter()
+            .map(|(name, source)| (name.to_string(), source.to_string()))
+            .collect())
+    }
+    #[cfg(not(feature = "bundled"))]
+    {
+        let dir = Path::new(BUILTIN_DIR);
+        let mut files = Vec::new();
+        cache::collect_files(dir, dir, &mut files)?;
+        files.sort();
+        files
+            .into_iter()
+            .map(|file| {
+                let path = dir.join(&file);
+                let source = fs::read_to_string(&path).map_err(|e| Error::io(&path, e))?;
+                Ok((file.to_string_lossy().replace('\\', "/"), source))
+            })
+            .collect()
+    }
+}
+
+/// An environment that loads templates from the first of `overrides`
+/// that has them, falling back to the built-in ones.
+pub fn environment(overrides: &[PathBuf]) -> Result<minijinja::Environment<'static>> {
+    let builtin: HashMap<String, String> = builtin()?.into_iter().collect();
+    let loaders: Vec<_> = overrides.iter().map(minijinja::path_loader).collect();
+
+    let mut env = minijinja::Environment::new();
+    env.set_loader(move |name| {
+        for loader in &loaders {
+            if let Some(source) = loader(name)? {
+                return Ok(Some(source));
+            }
+        }
+        Ok(builtin.get(name).cloned())
+    });
+    Ok(env)
+}
+
+/// Fingerprint of the templates `environment(overrides)` renders with.
+pub fn hash(overrides: &[PathBuf]) -> Result<String> {
+    #[cfg(feature = "bundled")]
+    let builtin = env!("OVERTURE_TEMPLATES_HASH").to_string();
+    #[cfg(not(feature = "bundled"))]
+    let builtin = cache::hash_dir(Path::new(BUILTIN_DIR))?;
+
+    let mut hashes = vec![builtin];
+    for dir in overrides {
+        hashes.push(cache::hash_dir(dir)?);
+    }
+    let hashes: Vec<&[u8]> = hashes.iter().map(|hash| hash.as_bytes()).collect();
+    Ok(cache::hash(&hashes))
+}
+
+// Name and source of every template `environment(overrides)` can load.
+fn sources(overrides: &[PathBuf]) -> Result<BTreeMap<String, String>> {
+    let mut sources: BTreeMap<String, String> = builtin()?.into_iter().collect();
+    for dir in overrides.iter().rev() {
+        let mut files = Vec::new();
+        cache::collect_files(dir, dir, &mut files)?;
+        for file in files {
+            let path = dir.join(&file);
+            let source = fs::read_to_string(&path).map_err(|e| Error::io(&path, e))?;
+            sources.insert(file.to_string_lossy().replace('\\', "/"), source);
+        }
+    }
+    Ok(sources)
+}
+
+/// Copies the templates `environment(overrides)` would use into `dir`, so
+/// they can be customised there, and returns the written files.
+/// Templates that already exist in `dir` are kept unless `force` is set.
+pub fn eject(dir: &Path, overrides: &[PathBuf], force: bool) -> Result<Vec<PathBuf>> {
+    let mut written = Vec::new();
+    for (name, source) in sources(overrides)? {
+        let path = dir.join(&name);
+        if path.exists() && !force {
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(|e| Error::io(parent, e))?;
+        }
+        fs::write(&path, source).map_err(|e| Error::io(&path, e))?;
+        written.push(path);
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overrides() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+
+        let project = dir.join("templates");
+        let theme = dir.join("theme");
+        fs::create_dir_all(&theme).unwrap();
+        fs::write(theme.join("about.html"), "theme about").unwrap();
+        let overrides = vec![project.clone(), theme.clone()];
+
+        let written = eject(&project, &overrides[1..], false).unwrap();
+        assert_eq!(written.len(), builtin().unwrap().len());
+        assert!(project.join("components/header.html").exists());
+        assert_eq!(
+            fs::read_to_string(project.join("about.html")).unwrap(),
+            "theme about"
+        );
+
+        let header = project.join("components/header.html");
+        fs::write(&header, "custom header").unwrap();
+        assert!(eject(&project, &overrides[1..], false).unwrap().is_empty());
+
+        let env = environment(&overrides).unwrap();
+        let template = env.get_template("components/header.html").unwrap();
+        assert_eq!(template.source(), "custom header");
+
+        // templates missing from the project fall back to the theme,
+        // then to the built-in ones
+        fs::remove_file(project.join("about.html")).unwrap();
+        fs::remove_file(project.join("index.html")).unwrap();
+        let env = environment(&overrides).unwrap();
+        assert_eq!(
+            env.get_template("about.html").unwrap().source(),
+            "theme about"
+        );
+        assert!(env.get_template("index.html").is_ok());
+    }
+}
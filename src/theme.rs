@@ -0,0 +1,57 @@
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+
+// Themes
+//
+// A theme is a directory below [paths] themes, selected with `theme = "<name>"`:
+//
+// themes/<name>/
+// ├── theme.toml  -- default config values, overridden by config.toml
+// ├── templates/  -- templates, overridden by the project's templates
+// └── statics/    -- static files, overridden by the project's statics
+//
+// Every part is optional. The built-in templates are the default theme.
+
+pub const DEFAULT_THEME: &str = "default";
+pub const THEME_CONFIG_FILE: &str = "theme.toml";
+
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub name: String,
+    pub dir: PathBuf,
+}
+
+impl Theme {
+    /// Looks up the theme `name` in `themes_dir`. The default theme has no
+    /// directory, so it is `None`.
+    pub fn find(themes_dir: &Path, name: &str, config_path: &Path) -> Result<Option<Theme>> {
+        if name == DEFAULT_THEME {
+            return Ok(None);
+        }
+        let dir = themes_dir.join(name);
+        if !dir.is_dir() {
+            return Err(Error::Config {
+                path: config_path.to_path_buf(),
+                location: None,
+                message: format!("theme {:?} not found in {}", name, themes_dir.display()),
+            });
+        }
+        Ok(Some(Theme {
+            name: name.to_string(),
+            dir,
+        }))
+    }
+
+    pub fn config(&self) -> PathBuf {
+        self.dir.join(THEME_CONFIG_FILE)
+    }
+
+    pub fn templates(&self) -> PathBuf {
+        self.dir.join("templates")
+    }
+
+    pub fn statics(&self) -> PathBuf {
+        self.dir.join("statics")
+    }
+}
@@ -0,0 +1,258 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use notify::event::{EventKind, ModifyKind};
+use notify::{RecursiveMode, Watcher as _};
+use tokio::sync::{broadcast, mpsc};
+
+use crate::builder::Builder;
+use crate::error::Result;
+use crate::project;
+use crate::templates;
+
+// Editors usually emit several events for a single save, so wait a little
+// and coalesce everything that arrives in the meantime into one rebuild.
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Change {
+    Config,    // config.toml
+    Templates, // 組み込みのテンプレートとその上書き
+    Articles,  // [paths] content 以下のファイル
+    Statics,   // [paths] statics 以下のファイル
+}
+
+impl Change {
+    fn name(self) -> &'static str {
+        match self {
+            Change::Config => "config",
+            Change::Templates => "templates",
+            Change::Articles => "articles",
+            Change::Statics => "statics",
+        }
+    }
+}
+
+struct Target {
+    path: PathBuf,  // 変更をこの Change とみなすパス
+    watch: PathBuf, // 実際に監視するパス
+    mode: RecursiveMode,
+    change: Change,
+}
+
+impl Target {
+    fn new(path: &Path, change: Change) -> Option<Self> {
+        let path = std::path::absolute(path).ok()?;
+        // a directory created after startup is picked up by watching the
+        // nearest one above it that exists
+        let mut existing = path.as_path();
+        while !existing.exists() {
+            existing = existing.parent()?;
+        }
+        // events name canonical paths
+        let canonical = fs::canonicalize(existing).ok()?;
+        let missing = path.strip_prefix(existing).ok()?;
+        let (watch, mode) = if !missing.as_os_str().is_empty() || canonical.is_dir() {
+            (canonical.clone(), RecursiveMode::Recursive)
+        } else {
+            // config.toml is often replaced rather than written in place,
+            // which drops a watch on the file itself, so watch its directory.
+            let dir = canonical.parent().unwrap_or(&canonical).to_path_buf();
+            (dir, RecursiveMode::NonRecursive)
+        };
+        Some(Target {
+            path: canonical.join(missing),
+            watch,
+            mode,
+            change,
+        })
+    }
+}
+
+pub struct Watcher {
+    targets: Vec<Target>,
+    reload: broadcast::Sender<()>,
+}
+
+impl Watcher {
+    pub fn new(reload: broadcast::Sender<()>, project: &project::Paths) -> Self {
+        Watcher {
+            targets: Watcher::targets(project),
+            reload,
+        }
+    }
+
+    // What to watch for `project`, whose [paths] and theme come from its config.
+    fn targets(project: &project::Paths) -> Vec<Target> {
+        let mut paths = vec![
+            (project.config.clone(), Change::Config),
+            (project.content.clone(), Change::Articles),
+            (project.statics.clone(), Change::Statics),
+            (project.templates.clone(), Change::Templates),
+        ];
+        if let Some(theme) = &project.theme {
+            paths.push((theme.config(), Change::Config));
+            paths.push((theme.templates(), Change::Templates));
+            paths.push((theme.statics(), Change::Statics));
+        }
+        paths.extend(templates::builtin_dir().map(|dir| (dir.to_path_buf(), Change::Templates)));
+
+        paths
+            .into_iter()
+            .filter_map(|(path, change)| Target::new(&path, change))
+            .collect()
+    }
+
+    fn classify(&self, path: &Path) -> Option<Change> {
+        self.targets
+            .iter()
+            .find(|target| path.starts_with(&target.path))
+            .map(|target| target.change)
+    }
+
+    fn collect(&self, event: &notify::Event, changes: &mut HashSet<Change>) {
+        // building reads the watched files, so access and metadata events
+        // must be ignored or every build would trigger the next one.
+        match event.kind {
+            EventKind::Create(_) | EventKind::Remove(_) => {}
+            EventKind::Modify(ModifyKind::Metadata(_)) => return,
+            EventKind::Modify(_) => {}
+            _ => return,
+        }
+        changes.extend(event.paths.iter().filter_map(|path| self.classify(path)));
+    }
+
+    async fn rebuild(&self, builder: &mut Builder, changes: &HashSet<Change>) -> Result<()> {
+        // fingerprinted statics change the URLs in every page
+        let statics_in_pages =
+            changes.contains(&Change::Statics) && builder.config().assets.fingerprint;
+        if changes.contains(&Change::Config)
+            || changes.contains(&Change::Templates)
+            || statics_in_pages
+        {
+            // config and templates are used by every page, so start over
+            *builder = builder.reload().await?;
+            return builder.build();
+        }
+
+        if changes.contains(&Change::Articles) {
+            builder.reload_articles()?;
+            builder.build_pages()?;
+        }
+        if changes.contains(&Change::Statics) {
+            builder.build_statics()?;
+            // pages with a changed image have new <picture> variants
+            if builder.config().images.enabled && !changes.contains(&Change::Articles) {
+                builder.build_pages()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Watches the paths of `project` instead of the current ones, as the
+    // config may have moved them or picked another theme.
+    fn rewatch(&mut self, watcher: &mut impl notify::Watcher, project: &project::Paths) {
+        for target in &self.targets {
+            let _ = watcher.unwatch(&target.watch);
+        }
+        self.targets = Watcher::targets(project);
+        for target in &self.targets {
+            if let Err(e) = watcher.watch(&target.watch, target.mode) {
+                eprintln!("{}: {}", target.watch.display(), e);
+            }
+        }
+    }
+
+    pub async fn watch(mut self, mut builder: Builder) -> notify::Result<()> {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut watcher =
+            notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+                if let Ok(event) = res {
+                    let _ = tx.send(event);
+                }
+            })?;
+        for target in &self.targets {
+            watcher.watch(&target.watch, target.mode)?;
+        }
+
+        while let Some(event) = rx.recv().await {
+            let mut changes = HashSet::new();
+            self.collect(&event, &mut changes);
+
+            tokio::time::sleep(DEBOUNCE).await;
+            while let Ok(event) = rx.try_recv() {
+                self.collect(&event, &mut changes);
+            }
+            if changes.is_empty() {
+                continue;
+            }
+
+            let mut names: Vec<&str> = changes.iter().map(|change| change.name()).collect();
+            names.sort();
+            match self.rebuild(&mut builder, &changes).await {
+                Ok(_) => {
+                    if changes.contains(&Change::Config) {
+                        self.rewatch(&mut watcher, builder.paths());
+                    }
+                    println!("Rebuild successful ({} changed)", names.join(", "));
+                    // no subscribers just means no browser tab is open
+                    let _ = self.reload.send(());
+                }
+                // the error comes first, as `path:line: message`
+                Err(e) => eprintln!("{}\nRebuild failed ({} changed)", e, names.join(", ")),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        let target = Target::new(&root.join("templates/components"), Change::Templates).unwrap();
+        assert_eq!(target.watch, root);
+        assert!(matches!(target.mode, RecursiveMode::Recursive));
+
+        let (reload, _) = broadcast::channel(1);
+        let watcher = Watcher {
+            targets: vec![target],
+            reload,
+        };
+        assert_eq!(
+            watcher.classify(&root.join("templates/components/header.html")),
+            Some(Change::Templates)
+        );
+        assert_eq!(watcher.classify(&root.join("templates/index.html")), None);
+    }
+
+    #[test]
+    fn test_theme_targets() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = fs::canonicalize(dir.path()).unwrap();
+        let config = crate::config::Config::default();
+        let mut paths = project::Paths::new(&root, root.join("config.toml"), &config.paths, None);
+
+        let (reload, _) = broadcast::channel(1);
+        let mut watcher = Watcher::new(reload, &paths);
+        let template = root.join("themes/minimal/templates/index.html");
+        assert_eq!(watcher.classify(&template), None);
+
+        // a theme picked in config.toml is watched after the reload
+        paths.theme = Some(crate::theme::Theme {
+            name: "minimal".to_string(),
+            dir: root.join("themes/minimal"),
+        });
+        let mut notify = notify::recommended_watcher(|_| {}).unwrap();
+        watcher.rewatch(&mut notify, &paths);
+        assert_eq!(watcher.classify(&template), Some(Change::Templates));
+    }
+}